@@ -1,29 +1,388 @@
-pub fn build_news_url(
-    tag: Option<&str>,
-    limit: Option<u32>,
-    offset: Option<u32>,
-    platform: Option<&str>,
-    fields: &[&str],
-) -> String {
-    let mut url = String::from("https://api.arcgames.com/v1.0/games/sto/news?");
-    if let Some(tag) = tag {
-        url.push_str(&format!("tag={}&", tag));
-    }
-    if let Some(limit) = limit {
-        url.push_str(&format!("limit={}&", limit));
-    }
-    if let Some(offset) = offset {
-        url.push_str(&format!("offset={}&", offset));
-    }
-    for field in fields {
-        url.push_str(&format!("field%5B%5D={}&", field));
-    }
-    if let Some(platform) = platform {
-        url.push_str(&format!("platform={}&", platform));
-    }
-    // Remove trailing '&' if present
-    if url.ends_with('&') {
-        url.pop();
-    }
-    url
+use std::fmt;
+use std::time::Duration;
+use reqwest::{StatusCode, Url};
+use reqwest::header::RETRY_AFTER;
+use serde::de::DeserializeOwned;
+use serenity::futures::stream::{self, Stream, StreamExt};
+
+use crate::logging::log_warn;
+use crate::news::{Article, ArticleResponse, News, NewsItem};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.arcgames.com/v1.0/games/sto/news";
+
+/// Number of items requested per page when walking past the first page.
+pub const PAGE_SIZE: u32 = 20;
+
+/// Fields requested for every news item, on top of the id, title and summary the API always returns.
+pub const NEWS_FIELDS: &[&str] = &["images.img_microsite_thumbnail", "platforms", "updated", "tags"];
+
+/// Longest wait before a retry, whatever the backoff or the API's `Retry-After` says.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Most time spent waiting on retries of one request, the error is returned rather than waiting longer.
+const MAX_TOTAL_RETRY_DELAY: Duration = Duration::from_secs(120);
+
+#[derive(Debug)]
+pub enum ArcApiError {
+    /// The request could not be sent or the response body could not be read
+    Network(reqwest::Error),
+    /// The API answered with a non-success status other than 429
+    Status(StatusCode),
+    /// The response body was not the expected JSON shape
+    Json(serde_json::Error),
+    /// The API answered 429, optionally telling us how long to back off
+    RateLimited(Option<Duration>),
+}
+
+impl fmt::Display for ArcApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArcApiError::Network(e) => write!(f, "network error: {}", e),
+            ArcApiError::Status(status) => write!(f, "unexpected HTTP status {}", status),
+            ArcApiError::Json(e) => write!(f, "unexpected response shape: {}", e),
+            ArcApiError::RateLimited(Some(wait)) => write!(f, "rate limited, retry after {}s", wait.as_secs()),
+            ArcApiError::RateLimited(None) => write!(f, "rate limited"),
+        }
+    }
+}
+
+impl std::error::Error for ArcApiError {}
+
+impl ArcApiError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            ArcApiError::Network(_) | ArcApiError::RateLimited(_) => true,
+            ArcApiError::Status(status) => status.is_server_error(),
+            ArcApiError::Json(_) => false,
+        }
+    }
+}
+
+/// Query parameters understood by the news endpoint.
+#[derive(Debug, Clone, Default)]
+pub struct NewsQuery<'a> {
+    pub tag: Option<&'a str>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+    pub platform: Option<&'a str>,
+    pub fields: &'a [&'a str],
+}
+
+pub fn build_news_url(base_url: &str, query: &NewsQuery) -> String {
+    let mut pairs: Vec<(&str, String)> = Vec::new();
+    if let Some(tag) = query.tag {
+        pairs.push(("tag", tag.to_string()));
+    }
+    if let Some(limit) = query.limit {
+        pairs.push(("limit", limit.to_string()));
+    }
+    if let Some(offset) = query.offset {
+        pairs.push(("offset", offset.to_string()));
+    }
+    for field in query.fields {
+        pairs.push(("field[]", field.to_string()));
+    }
+    if let Some(platform) = query.platform {
+        pairs.push(("platform", platform.to_string()));
+    }
+    // An unparsable base URL fails when the request is sent, with the URL in the error
+    let Ok(mut url) = Url::parse(base_url) else {
+        return base_url.to_string();
+    };
+    if !pairs.is_empty() {
+        url.query_pairs_mut().extend_pairs(pairs);
+    }
+    url.to_string()
+}
+
+/// Client for the ARC Games news API, sharing one connection pool across all callers.
+#[derive(Clone)]
+pub struct ArcClient {
+    http: reqwest::Client,
+    base_url: String,
+    max_retries: u32,
+    retry_delay: Duration,
+}
+
+impl ArcClient {
+    pub fn new(base_url: &str, timeout: Duration, max_retries: u32) -> Result<ArcClient, ArcApiError> {
+        let http = reqwest::Client::builder()
+            .timeout(timeout)
+            .connect_timeout(timeout)
+            .user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(ArcApiError::Network)?;
+        Ok(ArcClient {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            max_retries,
+            retry_delay: Duration::from_secs(1),
+        })
+    }
+
+    /// Fetch a single page of news, retrying transient failures with exponential backoff.
+    pub async fn fetch_news(&self, query: &NewsQuery<'_>) -> Result<News, ArcApiError> {
+        self.get_json(&build_news_url(&self.base_url, query)).await
+    }
+
+    /// Fetch the full content of one article, the body behind its playstartrekonline.com page.
+    pub async fn fetch_article(&self, id: u64) -> Result<Article, ArcApiError> {
+        let response: ArticleResponse = self.get_json(&format!("{}/{}", self.base_url, id)).await?;
+        Ok(response.details)
+    }
+
+    /// GET `url` and parse the JSON response, retrying transient failures with exponential backoff.
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, ArcApiError> {
+        let mut attempt = 0;
+        let mut waited = Duration::ZERO;
+        loop {
+            match self.fetch_once(url).await {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < self.max_retries && e.is_retryable() => {
+                    let Some(wait) = self.retry_wait(attempt, &e, waited) else {
+                        return Err(e);
+                    };
+                    waited += wait;
+                    attempt += 1;
+                    log_warn("ARC API request failed", &e, &format!("Retry {} of {} in {}ms", attempt, self.max_retries, wait.as_millis()));
+                    tokio::time::sleep(wait).await;
+                },
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Exponential backoff before retry number `attempt + 1`, capped at [`MAX_RETRY_DELAY`].
+    fn backoff(&self, attempt: u32) -> Duration {
+        2u32.checked_pow(attempt)
+            .map_or(MAX_RETRY_DELAY, |factor| self.retry_delay.saturating_mul(factor))
+            .min(MAX_RETRY_DELAY)
+    }
+
+    /// How long to wait before retrying after `error`, having `waited` so far, or `None` to give up.
+    fn retry_wait(&self, attempt: u32, error: &ArcApiError, waited: Duration) -> Option<Duration> {
+        let backoff = self.backoff(attempt);
+        let wait = match error {
+            ArcApiError::RateLimited(Some(retry_after)) => (*retry_after).max(backoff),
+            _ => backoff,
+        }.min(MAX_RETRY_DELAY);
+        (waited + wait <= MAX_TOTAL_RETRY_DELAY).then_some(wait)
+    }
+
+    async fn fetch_once<T: DeserializeOwned>(&self, url: &str) -> Result<T, ArcApiError> {
+        let resp = self.http.get(url).send().await.map_err(ArcApiError::Network)?;
+        let status = resp.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = resp.headers()
+                .get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(Duration::from_secs);
+            return Err(ArcApiError::RateLimited(retry_after));
+        }
+        if !status.is_success() {
+            return Err(ArcApiError::Status(status));
+        }
+        let text = resp.text().await.map_err(ArcApiError::Network)?;
+        serde_json::from_str::<T>(&text).map_err(ArcApiError::Json)
+    }
+
+    /// Walk the news history page by page, starting at `query.offset` and using `query.limit` as the page size.
+    ///
+    /// The stream ends after the first empty or short page, or after the first error.
+    pub fn news_pages<'a>(&'a self, query: NewsQuery<'a>) -> impl Stream<Item = Result<News, ArcApiError>> + 'a {
+        let page_size = query.limit.unwrap_or(PAGE_SIZE).max(1);
+        let start = query.offset.unwrap_or(0);
+        stream::unfold(Some(start), move |offset| {
+            let query = query.clone();
+            async move {
+                let offset = offset?;
+                let page_query = NewsQuery { limit: Some(page_size), offset: Some(offset), ..query };
+                match self.fetch_news(&page_query).await {
+                    Ok(news) if news.is_empty() => None,
+                    Ok(news) => {
+                        let next = if (news.len() as u32) < page_size { None } else { Some(offset + page_size) };
+                        Some((Ok(news), next))
+                    },
+                    Err(e) => Some((Err(e), None)),
+                }
+            }
+        })
+    }
+
+    /// The `limit` most recent news items, optionally restricted to one tag.
+    pub async fn latest_news(&self, tag: Option<&str>, limit: u32) -> Result<News, ArcApiError> {
+        let query = NewsQuery {
+            tag,
+            limit: Some(limit.min(PAGE_SIZE)),
+            offset: Some(0),
+            platform: None,
+            fields: NEWS_FIELDS,
+        };
+        self.collect_news(query, limit as usize).await
+    }
+
+    /// Collect up to `max_items` news items, fetching as many pages as needed.
+    pub async fn collect_news(&self, query: NewsQuery<'_>, max_items: usize) -> Result<News, ArcApiError> {
+        let mut items: Vec<NewsItem> = Vec::new();
+        let mut pages = Box::pin(self.news_pages(query));
+        while items.len() < max_items {
+            match pages.next().await {
+                Some(page) => items.extend(page?.iter().cloned()),
+                None => break,
+            }
+        }
+        items.truncate(max_items);
+        Ok(News::from_items(items))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, ResponseTemplate};
+    use crate::test_support::{article_fixture, news_fixture, MockUpstream, NEWS_PATH};
+
+    #[test]
+    fn builds_url_with_all_parameters() {
+        let query = NewsQuery { tag: Some("patch-notes"), limit: Some(5), offset: Some(10), platform: Some("pc"), fields: &["updated"] };
+        assert_eq!(
+            build_news_url("http://localhost/news", &query),
+            "http://localhost/news?tag=patch-notes&limit=5&offset=10&field%5B%5D=updated&platform=pc"
+        );
+        assert_eq!(build_news_url("http://localhost/news", &NewsQuery::default()), "http://localhost/news");
+        let query = NewsQuery { tag: Some("dev blogs&more"), platform: Some("pc#1"), ..NewsQuery::default() };
+        assert_eq!(build_news_url("http://localhost/news", &query), "http://localhost/news?tag=dev+blogs%26more&platform=pc%231");
+    }
+
+    #[test]
+    fn caps_the_backoff() {
+        let client = ArcClient::new("http://localhost/news", Duration::from_secs(5), 40).unwrap();
+        assert_eq!(client.backoff(0), Duration::from_secs(1));
+        assert_eq!(client.backoff(3), Duration::from_secs(8));
+        assert_eq!(client.backoff(6), MAX_RETRY_DELAY);
+        assert_eq!(client.backoff(32), MAX_RETRY_DELAY);
+        assert_eq!(client.backoff(u32::MAX), MAX_RETRY_DELAY);
+
+        // Retry-After is honoured up to the same cap, and retrying stops once the total would be exceeded
+        let rate_limited = ArcApiError::RateLimited(Some(Duration::from_secs(86400)));
+        assert_eq!(client.retry_wait(0, &rate_limited, Duration::ZERO), Some(MAX_RETRY_DELAY));
+        assert_eq!(client.retry_wait(1, &rate_limited, MAX_RETRY_DELAY), Some(MAX_RETRY_DELAY));
+        assert_eq!(client.retry_wait(2, &rate_limited, MAX_RETRY_DELAY * 2), None);
+        assert_eq!(client.retry_wait(2, &ArcApiError::RateLimited(None), Duration::ZERO), Some(Duration::from_secs(4)));
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limits() {
+        let upstream = MockUpstream::start().await;
+        Mock::given(method("GET"))
+            .and(path(NEWS_PATH))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(upstream.server())
+            .await;
+        upstream.serve_news(news_fixture()).await;
+
+        let mut client = ArcClient::new(&upstream.api_url(), Duration::from_secs(5), 2).unwrap();
+        client.retry_delay = Duration::from_millis(1);
+        assert_eq!(client.fetch_news(&NewsQuery::default()).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let upstream = MockUpstream::start().await;
+        Mock::given(method("GET"))
+            .and(path(NEWS_PATH))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(upstream.server())
+            .await;
+        upstream.serve_news(news_fixture()).await;
+
+        let client = ArcClient::new(&upstream.api_url(), Duration::from_secs(5), 1).unwrap();
+        assert_eq!(client.fetch_news(&NewsQuery::default()).await.unwrap().len(), 3);
+
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let upstream = MockUpstream::start().await;
+        Mock::given(method("GET"))
+            .and(path(NEWS_PATH))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(1)
+            .mount(upstream.server())
+            .await;
+
+        let client = ArcClient::new(&upstream.api_url(), Duration::from_secs(5), 3).unwrap();
+        assert!(matches!(client.fetch_news(&NewsQuery::default()).await, Err(ArcApiError::Status(StatusCode::NOT_FOUND))));
+    }
+
+    #[tokio::test]
+    async fn collects_news_across_pages() {
+        let upstream = MockUpstream::start().await;
+        let all: serde_json::Value = serde_json::from_str(&news_fixture()).unwrap();
+        let items = all["news"].as_array().unwrap();
+        for (offset, page) in [(0, &items[0..2]), (2, &items[2..3])] {
+            Mock::given(method("GET"))
+                .and(path(NEWS_PATH))
+                .and(query_param("offset", offset.to_string()))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "news": page })))
+                .mount(upstream.server())
+                .await;
+        }
+
+        let client = ArcClient::new(&upstream.api_url(), Duration::from_secs(5), 0).unwrap();
+        let query = NewsQuery { limit: Some(2), ..NewsQuery::default() };
+        let ids: Vec<u64> = client.collect_news(query.clone(), 10).await.unwrap().iter().map(|item| item.get_id()).collect();
+        assert_eq!(ids, vec![11100001, 11100002, 11100003]);
+        assert_eq!(client.collect_news(query, 1).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn stops_paging_at_an_empty_page_or_error() {
+        let upstream = MockUpstream::start().await;
+        let all: serde_json::Value = serde_json::from_str(&news_fixture()).unwrap();
+        let items = all["news"].as_array().unwrap();
+        Mock::given(method("GET"))
+            .and(path(NEWS_PATH))
+            .and(query_param("offset", "0"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "news": &items[0..3] })))
+            .mount(upstream.server())
+            .await;
+        Mock::given(method("GET"))
+            .and(path(NEWS_PATH))
+            .and(query_param("offset", "3"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "news": [] })))
+            .expect(1)
+            .mount(upstream.server())
+            .await;
+
+        let client = ArcClient::new(&upstream.api_url(), Duration::from_secs(5), 0).unwrap();
+        // A full last page needs one more request to find the end
+        let pages: Vec<_> = client.news_pages(NewsQuery { limit: Some(3), ..NewsQuery::default() }).collect().await;
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].as_ref().unwrap().len(), 3);
+
+        let pages: Vec<_> = client.news_pages(NewsQuery { limit: Some(3), offset: Some(100), ..NewsQuery::default() }).collect().await;
+        assert!(matches!(pages.as_slice(), [Err(ArcApiError::Status(StatusCode::NOT_FOUND))]));
+    }
+
+    #[tokio::test]
+    async fn fetches_article_by_id() {
+        let upstream = MockUpstream::start().await;
+        upstream.serve_article(11100002, article_fixture()).await;
+        let client = ArcClient::new(&upstream.api_url(), Duration::from_secs(5), 0).unwrap();
+
+        let article = client.fetch_article(11100002).await.unwrap();
+        assert_eq!(article.title, "Console Patch Notes for Today");
+        assert_eq!(article.pages(), vec![
+            "## General\n\nFixed an issue that caused some **Bridge Officer** abilities to fail.\n\n### Systems\n\n\
+            - Resolved a crash when opening the [event](https://playstartrekonline.com/en/news/article/11100001) window.\n\
+            - Improved load times.".to_string()
+        ]);
+        assert!(matches!(client.fetch_article(1).await, Err(ArcApiError::Status(StatusCode::NOT_FOUND))));
+    }
 }
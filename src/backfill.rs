@@ -0,0 +1,134 @@
+// One-off import of the news history into the local archive, run with `stobot backfill`
+use std::fmt;
+use serenity::futures::StreamExt;
+
+use crate::arc_api::{ArcApiError, ArcClient, NewsQuery, NEWS_FIELDS, PAGE_SIZE};
+use crate::logging::log_info;
+use crate::store::{BackfillState, NewsArchive, StoreError};
+
+#[derive(Debug)]
+pub enum BackfillError {
+    Api(ArcApiError),
+    Store(StoreError),
+}
+
+impl fmt::Display for BackfillError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackfillError::Api(e) => write!(f, "ARC API error: {}", e),
+            BackfillError::Store(e) => write!(f, "Archive error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BackfillError {}
+
+impl From<ArcApiError> for BackfillError {
+    fn from(e: ArcApiError) -> Self {
+        BackfillError::Api(e)
+    }
+}
+
+impl From<StoreError> for BackfillError {
+    fn from(e: StoreError) -> Self {
+        BackfillError::Store(e)
+    }
+}
+
+/// What a backfill did.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct BackfillReport {
+    pub pages: usize,
+    pub items: usize,
+    /// Items that were not archived before
+    pub added: usize,
+    /// Whether the whole history has been archived now
+    pub complete: bool,
+}
+
+/// Page through the news history newest first and archive every item, stopping after `max_pages` pages if given.
+///
+/// Until the whole history was archived once, each run continues where the last one stopped, however much of the
+/// newest news the poller or searches archived meanwhile. After that the walk ends at the first page that adds
+/// nothing new, unless `full` is set to refresh the whole history.
+pub async fn backfill(arc: &ArcClient, archive: &dyn NewsArchive, max_pages: Option<usize>, full: bool) -> Result<BackfillReport, BackfillError> {
+    let state = archive.backfill_state()?;
+    let start = match state {
+        BackfillState::Partial(offset) if !full => offset,
+        _ => 0,
+    };
+    let query = NewsQuery {
+        limit: Some(PAGE_SIZE),
+        offset: Some(start),
+        fields: NEWS_FIELDS,
+        ..NewsQuery::default()
+    };
+    let max_pages = max_pages.unwrap_or(usize::MAX);
+    let mut report = BackfillReport::default();
+    let mut pages = Box::pin(arc.news_pages(query).take(max_pages));
+    let mut offset = start;
+    while let Some(page) = pages.next().await {
+        let page = page?;
+        let added = archive.archive_news(page.as_slice())?;
+        report.pages += 1;
+        report.items += page.len();
+        report.added += added;
+        offset += page.len() as u32;
+        log_info("Backfilled news page", Some(&format!("Page:{} Items:{} Added:{}", report.pages, page.len(), added)));
+        match state {
+            // Newer items only push older ones to later offsets, so resuming here skips nothing
+            BackfillState::Partial(_) => archive.set_backfill_state(BackfillState::Partial(offset))?,
+            BackfillState::Complete if added == 0 && !full => break,
+            BackfillState::Complete => {},
+        }
+    }
+    // Running out of pages before `max_pages` means the oldest item was reached
+    if state == BackfillState::Complete || report.pages < max_pages {
+        archive.set_backfill_state(BackfillState::Complete)?;
+        report.complete = true;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+    use crate::news::News;
+    use crate::test_support::{memory_store, news_fixture, news_history, MockUpstream};
+
+    #[tokio::test]
+    async fn archives_the_history_once() {
+        let upstream = MockUpstream::start().await;
+        upstream.serve_news(news_fixture()).await;
+        let client = ArcClient::new(&upstream.api_url(), Duration::from_secs(5), 0).unwrap();
+        let store = memory_store();
+
+        assert_eq!(backfill(&client, &store, None, false).await.unwrap(), BackfillReport { pages: 1, items: 3, added: 3, complete: true });
+        assert_eq!(store.archived_count().unwrap(), 3);
+        assert_eq!(backfill(&client, &store, None, true).await.unwrap(), BackfillReport { pages: 1, items: 3, added: 0, complete: true });
+        assert_eq!(backfill(&client, &store, Some(0), false).await.unwrap(), BackfillReport { complete: true, ..BackfillReport::default() });
+    }
+
+    #[tokio::test]
+    async fn continues_past_news_archived_by_the_poller() {
+        let upstream = MockUpstream::start().await;
+        let items = news_history(PAGE_SIZE as u64 * 2 + 5);
+        upstream.serve_news_pages(&items).await;
+        let client = ArcClient::new(&upstream.api_url(), Duration::from_secs(5), 0).unwrap();
+        let store = memory_store();
+        // The poller archived the newest page before anyone ran a backfill
+        let newest: News = serde_json::from_value(serde_json::json!({ "news": &items[..PAGE_SIZE as usize] })).unwrap();
+        store.archive_news(newest.as_slice()).unwrap();
+
+        // An interrupted run resumes where it stopped
+        assert_eq!(backfill(&client, &store, Some(2), false).await.unwrap(), BackfillReport { pages: 2, items: 40, added: 20, complete: false });
+        assert_eq!(store.backfill_state().unwrap(), BackfillState::Partial(40));
+        assert_eq!(backfill(&client, &store, None, false).await.unwrap(), BackfillReport { pages: 1, items: 5, added: 5, complete: true });
+        assert_eq!(store.archived_count().unwrap(), items.len());
+
+        // Once complete, a run stops at the first page it already knows
+        assert_eq!(backfill(&client, &store, None, false).await.unwrap(), BackfillReport { pages: 1, items: 20, added: 0, complete: true });
+        assert_eq!(store.backfill_state().unwrap(), BackfillState::Complete);
+    }
+}
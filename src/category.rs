@@ -0,0 +1,171 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+/// A kind of news item, derived from the tags the ARC Games API attaches to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Category {
+    PatchNotes,
+    Events,
+    Store,
+    DevBlogs,
+    /// Anything that doesn't fit one of the other categories
+    Other,
+}
+
+impl Category {
+    pub const ALL: [Category; 5] = [Category::PatchNotes, Category::Events, Category::Store, Category::DevBlogs, Category::Other];
+
+    /// The name stored per channel and typed into commands.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::PatchNotes => "patch-notes",
+            Category::Events => "events",
+            Category::Store => "store",
+            Category::DevBlogs => "dev-blogs",
+            Category::Other => "other",
+        }
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Category::PatchNotes => "Patch Notes",
+            Category::Events => "Events",
+            Category::Store => "Store",
+            Category::DevBlogs => "Dev Blogs",
+            Category::Other => "Other",
+        }
+    }
+
+    /// API tags that put an item into this category.
+    fn tags(&self) -> &'static [&'static str] {
+        match self {
+            Category::PatchNotes => &["patch-notes", "patch notes", "patchnotes", "release-notes"],
+            Category::Events => &["events", "event", "in-game-events"],
+            Category::Store => &["store", "zen-store", "c-store", "sale", "sales", "promotions"],
+            Category::DevBlogs => &["dev-blogs", "dev-blog", "dev blog", "developer-blog", "dev-diary"],
+            Category::Other => &[],
+        }
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            Category::PatchNotes => &["patch-notes", "patchnotes", "patch notes", "patches", "patch"],
+            Category::Events => &["events", "event"],
+            Category::Store => &["store", "sales", "sale", "zen-store"],
+            Category::DevBlogs => &["dev-blogs", "devblogs", "dev blogs", "dev-blog", "blogs", "blog"],
+            Category::Other => &["other", "general", "news"],
+        }
+    }
+
+    pub fn from_alias(name: &str) -> Option<Category> {
+        let name = name.trim().to_lowercase();
+        Category::ALL.into_iter().find(|c| c.aliases().contains(&name.as_str()))
+    }
+
+    /// Categories matching a set of API tags. Never empty, untagged or unknown items are `Other`.
+    pub fn from_tags<S: AsRef<str>>(tags: &[S]) -> BTreeSet<Category> {
+        let mut categories: BTreeSet<Category> = tags.iter()
+            .map(|t| t.as_ref().trim().to_lowercase())
+            .flat_map(|t| Category::ALL.into_iter().filter(move |c| c.tags().contains(&t.as_str())))
+            .collect();
+        if categories.is_empty() {
+            categories.insert(Category::Other);
+        }
+        categories
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.display_name())
+    }
+}
+
+impl FromStr for Category {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Category::from_alias(s).ok_or_else(|| format!(
+            "Unknown category `{}`. Valid categories are {}, or `all`.",
+            s.trim(),
+            Category::ALL.iter().map(|c| format!("`{}`", c.name())).collect::<Vec<_>>().join(", ")
+        ))
+    }
+}
+
+/// Parse a comma-separated category list as typed into a slash command option.
+///
+/// `all` yields an empty set, which means the channel gets every category.
+pub fn parse_category_list(input: &str) -> Result<BTreeSet<String>, String> {
+    if input.trim().eq_ignore_ascii_case("all") {
+        return Ok(BTreeSet::new());
+    }
+    let mut categories = BTreeSet::new();
+    for name in input.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        categories.insert(name.parse::<Category>()?.name().to_string());
+    }
+    if categories.is_empty() {
+        return Err("Category list cannot be empty, use `all` to receive everything.".to_string());
+    }
+    Ok(categories)
+}
+
+/// Human readable form of a stored category set.
+pub fn describe_categories(categories: &BTreeSet<String>) -> String {
+    if categories.is_empty() {
+        "All".to_string()
+    } else {
+        categories.iter()
+            .map(|c| Category::from_alias(c).map_or(c.as_str(), |c| c.display_name()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Autocomplete suggestions for a comma-separated category list, completing the last entry.
+pub fn complete_category_list(input: &str) -> Vec<(String, String)> {
+    let (done, current) = match input.rsplit_once(',') {
+        Some((done, current)) => (Some(done), current),
+        None => (None, input),
+    };
+    let chosen: BTreeSet<Category> = done
+        .map(|d| d.split(',').filter_map(Category::from_alias).collect())
+        .unwrap_or_default();
+    let current = current.trim().to_lowercase();
+
+    let mut suggestions: Vec<(String, String)> = Category::ALL.iter()
+        .filter(|c| !chosen.contains(c))
+        .filter(|c| current.is_empty() || c.aliases().iter().any(|a| a.starts_with(&current)) || c.display_name().to_lowercase().starts_with(&current))
+        .map(|c| {
+            let mut list: Vec<Category> = chosen.iter().copied().collect();
+            list.push(*c);
+            let label = list.iter().map(|c| c.display_name()).collect::<Vec<_>>().join(", ");
+            let value = list.iter().map(|c| c.name()).collect::<Vec<_>>().join(",");
+            (label, value)
+        })
+        .collect();
+    if done.is_none() && "all".starts_with(&current) {
+        suggestions.insert(0, ("All categories".to_string(), "all".to_string()));
+    }
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_tags_to_categories() {
+        assert_eq!(Category::from_tags(&["star-trek-online", "Patch-Notes"]), BTreeSet::from([Category::PatchNotes]));
+        assert_eq!(Category::from_tags(&["star-trek-online"]), BTreeSet::from([Category::Other]));
+        assert_eq!(Category::from_tags::<&str>(&[]), BTreeSet::from([Category::Other]));
+    }
+
+    #[test]
+    fn parses_category_lists() {
+        assert_eq!(parse_category_list("Patch Notes, events").unwrap(), BTreeSet::from(["patch-notes".to_string(), "events".to_string()]));
+        assert!(parse_category_list("ALL").unwrap().is_empty());
+        assert!(parse_category_list("lockboxes").is_err());
+    }
+}
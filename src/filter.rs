@@ -0,0 +1,110 @@
+use std::fmt;
+use regex::{Regex, RegexBuilder};
+
+/// Whether matching items are the only ones posted, or never posted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FilterMode {
+    Include,
+    Exclude,
+}
+
+impl FilterMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterMode::Include => "include",
+            FilterMode::Exclude => "exclude",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FilterMode> {
+        match name.trim().to_lowercase().as_str() {
+            "include" => Some(FilterMode::Include),
+            "exclude" => Some(FilterMode::Exclude),
+            _ => None,
+        }
+    }
+}
+
+/// A keyword or regular expression matched against the title and summary of news items.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FilterRule {
+    pub mode: FilterMode,
+    pub pattern: String,
+    /// Keywords match anywhere in the text, ignoring case; regexes are used as given (case-insensitive too)
+    pub is_regex: bool,
+}
+
+impl FilterRule {
+    /// The rule as a case-insensitive regex, failing only for invalid regex patterns.
+    pub fn compile(&self) -> Result<Regex, regex::Error> {
+        let pattern = if self.is_regex { self.pattern.clone() } else { regex::escape(self.pattern.trim()) };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(true)
+            .size_limit(1 << 20)
+            .build()
+    }
+}
+
+impl fmt::Display for FilterRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_regex {
+            write!(f, "{} regex `{}`", self.mode.name(), self.pattern)
+        } else {
+            write!(f, "{} keyword `{}`", self.mode.name(), self.pattern)
+        }
+    }
+}
+
+/// The compiled filter rules of one channel.
+///
+/// An item passes if it matches no exclude rule and, when there are include rules, at least one of them.
+#[derive(Default)]
+pub struct FilterSet {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl FilterSet {
+    pub fn new(rules: &[FilterRule]) -> Result<FilterSet, regex::Error> {
+        let mut set = FilterSet::default();
+        for rule in rules {
+            let regex = rule.compile()?;
+            match rule.mode {
+                FilterMode::Include => set.include.push(regex),
+                FilterMode::Exclude => set.exclude.push(regex),
+            }
+        }
+        Ok(set)
+    }
+
+    pub fn matches(&self, title: &str, summary: &str) -> bool {
+        let hit = |regex: &Regex| regex.is_match(title) || regex.is_match(summary);
+        !self.exclude.iter().any(hit) && (self.include.is_empty() || self.include.iter().any(hit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(mode: FilterMode, pattern: &str, is_regex: bool) -> FilterRule {
+        FilterRule { mode, pattern: pattern.to_string(), is_regex }
+    }
+
+    #[test]
+    fn applies_include_and_exclude_rules() {
+        let filters = FilterSet::new(&[
+            rule(FilterMode::Include, "phoenix", false),
+            rule(FilterMode::Include, r"lock\s?box", true),
+            rule(FilterMode::Exclude, "Sale", false),
+        ]).unwrap();
+        assert!(filters.matches("Phoenix Prize Pack returns", ""));
+        assert!(filters.matches("New event", "A new Lockbox is coming."));
+        assert!(!filters.matches("Lockbox Sale Weekend", ""));
+        assert!(!filters.matches("Featured Episode", "Nothing to see here."));
+        assert!(FilterSet::new(&[]).unwrap().matches("Anything", ""));
+        assert!(FilterSet::new(&[rule(FilterMode::Include, "(", true)]).is_err());
+        // Keywords are literal, not patterns
+        assert!(FilterSet::new(&[rule(FilterMode::Include, "(", false)]).unwrap().matches("Tribble (Rare)", ""));
+    }
+}
@@ -1,242 +1,296 @@
-use std::collections::{BTreeSet, BTreeMap, HashSet};
-use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
-use std::sync::{Mutex, MutexGuard};
-use std::time::Duration;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
 use serenity::async_trait;
-use serenity::builder::{CreateCommand, CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage, GetMessages, CreateEmbed, CreateEmbedFooter, CreateInteractionResponseFollowup, CreateAttachment};
+use serenity::builder::{CreateCommand, CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage, CreateEmbed, CreateInteractionResponseFollowup, CreateAutocompleteResponse};
 use serenity::all::{
     Interaction, CommandOptionType,
-    CommandInteraction, Command,
+    CommandInteraction, Command, ResolvedOption, ResolvedValue,
 };
 use serenity::model::channel::Message;
 use serenity::model::gateway::Ready;
-use serenity::model::id::ChannelId;
 use serenity::prelude::*;
 // use serenity::futures::Future;
-use regex::Regex;
-use tokio::time::{self};
 use chrono::Local;
-use scraper::{Html, Selector};
 
 use crate::news::News;
-use crate::arc_api::build_news_url;
+use crate::arc_api::ArcClient;
+use crate::category::{complete_category_list, describe_categories, parse_category_list, Category};
+use crate::filter::{FilterMode, FilterRule};
+use crate::ping::{PingRule, PingTarget};
+use crate::logging::{log_error, log_info};
+use crate::pager::Pager;
+use crate::platform::{complete_platform_list, parse_platform_list};
+use crate::platform::Platform;
+use crate::poller::{PollMetrics, PostMode, POST_MODE_SETTING};
+use crate::revision::{UpdateMode, UPDATE_MODE_SETTING};
+use crate::search::{search_news, SearchQuery};
+use crate::store::{Store, StoreError, Subscription};
+use crate::wiki::{WikiClient, WikiVisibility};
 
-// Helper for logging errors with consistent format
-fn log_error(context: &str, error: impl std::fmt::Display) {
-    eprintln!("CEF:0|stobot|{}|{}|ERROR|Error|msg={} | Context: {}. time={}", 
-        env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), error, context, Local::now().to_rfc3339());
-}
+/// News items shown per page of `/stobot_news` and `/stobot_patchnotes`, at most [`crate::pager::MAX_EMBEDS_PER_PAGE`].
+const NEWS_PAGE_SIZE: usize = 5;
 
-// Helper for logging info with consistent format
-fn log_info(message: &str, details: Option<&str>) {
-    if let Some(details) = details {
-        println!("CEF:0|stobot|{}|{}|INFO|{}|msg={} time={}", 
-            env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), message, details, Local::now().to_rfc3339());
-    } else {
-        println!("CEF:0|stobot|{}|{}|INFO|{}|time={}", 
-            env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), message, Local::now().to_rfc3339());
-    }
+/// Settings that come from the command line and stay fixed for the lifetime of the bot.
+pub struct HandlerConfig {
+    pub poll_period: u64,
+    /// Platforms for new registrations and for channels without a stored set
+    pub default_platforms: BTreeSet<String>,
 }
 
 pub struct Handler {
     poll_period: u64,
-    poll_count: u64,
-    channel_ids: Mutex<HashSet<u64>>,
-    channel_txt_path: String,
-    fresh_seconds: u64,
-    msg_count: u8,
-    platforms: Mutex<BTreeMap<u64, BTreeSet<String>>>, // Map of channel ID to platforms
+    default_platforms: BTreeSet<String>,
+    store: Arc<dyn Store>,
+    arc: ArcClient,
+    wiki: WikiClient,
+    poll_metrics: Arc<PollMetrics>,
+    pager: Pager,
 }
 
 impl Handler {
-    pub fn new(poll_period: u64, poll_count: u64, channel_txt_path: String, fresh_seconds: u64, msg_count: u8, _default_platforms: BTreeSet<String>) -> Handler {
-        // Default platforms to include all three: pc, xbox, ps
-        let default_platforms = BTreeSet::from_iter(vec!["pc".to_string(), "xbox".to_string(), "ps".to_string()]);
+    pub fn new(config: HandlerConfig, store: Arc<dyn Store>, arc: ArcClient, wiki: WikiClient, poll_metrics: Arc<PollMetrics>) -> Handler {
         let handler = Handler {
-            poll_period,
-            poll_count,
-            channel_ids: Mutex::new(HashSet::new()),
-            channel_txt_path,
-            fresh_seconds,
-            msg_count,
-            platforms: Mutex::new(BTreeMap::new()),
+            poll_period: config.poll_period,
+            default_platforms: config.default_platforms,
+            store,
+            arc,
+            wiki,
+            poll_metrics,
+            pager: Pager::default(),
         };
-        log_info("Reading channels file", Some(&handler.channel_txt_path));
-        
-        if let Ok(file) = File::open(&handler.channel_txt_path) {
-            let reader = BufReader::new(file);
-            let mut channel_ids = handler.channel_ids.lock().unwrap();
-            let mut platforms_map = handler.platforms.lock().unwrap();
-            
-            for line in reader.lines() {
-                if let Ok(line) = line {
-                    if line.starts_with("channel:") {
-                        // Parse channel entry: channel:123456789|pc,ps,xbox
-                        let parts: Vec<&str> = line.trim_start_matches("channel:").split('|').collect();
-                        if parts.len() == 2 {
-                            if let Ok(channel_id) = parts[0].parse::<u64>() {
-                                let platform_list: Vec<String> = parts[1].split(',')
-                                    .map(|s| s.trim().to_string())
-                                    .filter(|s| !s.is_empty())
-                                    .collect();
-                                
-                                let platform_set = if !platform_list.is_empty() {
-                                    BTreeSet::from_iter(platform_list)
-                                } else {
-                                    default_platforms.clone()
-                                };
-                                
-                                // Add to channel_ids and platforms
-                                channel_ids.insert(channel_id);
-                                platforms_map.insert(channel_id, platform_set.clone());
-                                
-                                log_info("Loaded channel", Some(&format!("ID:{} Platforms:{:?}", channel_id, platform_set)));
-                            }
-                        }
-                    }
-                }
-            }
-        } else {
-            log_error("Reading channels file", format!("Could not open file: {}", handler.channel_txt_path));
-        }
-        
+
         log_info("Channels", None);
         let channels = handler.get_channels();
         for channel in channels.iter() {
             print!(" {channel}");
         }
         println!(" end=time:{}", Local::now().to_rfc3339());
-        
+
         handler
     }
-    
-    fn add_channel_internal(&self, id: u64, platforms: BTreeSet<String>, platforms_map: &mut MutexGuard<BTreeMap<u64, BTreeSet<String>>>) {
-        let mut channel_ids = self.channel_ids.lock().unwrap();
-        channel_ids.insert(id);
-        platforms_map.insert(id, platforms);
-        self.write_channels_to_file(&channel_ids, platforms_map);
-    }
 
-    pub fn get_channels(&self) -> HashSet<u64> {
-        self.channel_ids.lock().unwrap().clone()
+    pub fn get_channels(&self) -> BTreeSet<u64> {
+        match self.store.list_subscriptions() {
+            Ok(subscriptions) => subscriptions.into_iter().map(|s| s.channel_id).collect(),
+            Err(e) => {
+                log_error("Listing subscribed channels", e);
+                BTreeSet::new()
+            }
+        }
     }
 
-    fn write_channels_to_file(&self, channel_ids: &MutexGuard<HashSet<u64>>, platforms_map: &MutexGuard<BTreeMap<u64, BTreeSet<String>>>) {
-        let mut file = File::create(&self.channel_txt_path).expect(format!(
-            "Couldn't open {}", self.channel_txt_path).as_str());
-        
-        // Write each channel and its platforms
-        for id in channel_ids.iter() {
-            let default_platforms = BTreeSet::new();
-            let platforms = platforms_map.get(id).unwrap_or(&default_platforms);
-            let platforms_str = platforms.iter()
-                .map(|s| s.to_string())
-                .collect::<Vec<String>>()
-                .join(",");
-            
-            writeln!(file, "channel:{}|{}", id, platforms_str).expect(format!(
-                "Couldn't write to {}", self.channel_txt_path).as_str());
+    fn is_registered(&self, channel_id: u64) -> bool {
+        match self.store.get_subscription(channel_id) {
+            Ok(subscription) => subscription.is_some(),
+            Err(e) => {
+                log_error("Looking up channel subscription", e);
+                false
+            }
         }
     }
 
-    pub fn add_channel(&self, id: u64) {
-        let mut platforms_map = self.platforms.lock().unwrap();
-        // Use all three platforms by default
-        let default_platforms = BTreeSet::from_iter(vec!["pc".to_string(), "xbox".to_string(), "ps".to_string()]);
-        self.add_channel_internal(id, default_platforms, &mut platforms_map);
+    /// Subscribe the channel with the default settings. Returns `false` if it already was, keeping its settings.
+    pub fn add_channel(&self, id: u64, guild_id: Option<u64>) -> Result<bool, StoreError> {
+        self.store.insert_subscription(&Subscription {
+            channel_id: id,
+            guild_id,
+            platforms: self.default_platforms.clone(),
+            categories: BTreeSet::new(),
+            filters: Vec::new(),
+            pings: Vec::new(),
+            settings: BTreeMap::new(),
+        })
     }
 
-    fn remove_channel(&self, id: u64) {
-        let mut channel_ids = self.channel_ids.lock().unwrap();
-        channel_ids.remove(&id);
-        
-        let mut platforms_map = self.platforms.lock().unwrap();
-        platforms_map.remove(&id);
-        
-        self.write_channels_to_file(&channel_ids, &platforms_map);
+    fn remove_channel(&self, id: u64) -> Result<bool, StoreError> {
+        self.store.remove_subscription(id)
     }
 
     fn get_channel_platforms(&self, channel_id: u64) -> BTreeSet<String> {
-        let platforms_map = self.platforms.lock().unwrap();
-        platforms_map.get(&channel_id)
-            .cloned()
-            .unwrap_or_else(|| BTreeSet::from_iter(vec!["pc".to_string(), "xbox".to_string(), "ps".to_string()]))
+        match self.store.get_subscription(channel_id) {
+            Ok(Some(subscription)) if !subscription.platforms.is_empty() => subscription.platforms,
+            Ok(_) => self.default_platforms.clone(),
+            Err(e) => {
+                log_error("Loading channel platforms", e);
+                self.default_platforms.clone()
+            }
+        }
     }
 
-    fn update_channel_platforms(&self, channel_id: u64, new_platforms: BTreeSet<String>) {
-        let mut platforms_map = self.platforms.lock().unwrap();
-        platforms_map.insert(channel_id, new_platforms);
-        
-        let channel_ids = self.channel_ids.lock().unwrap();
-        self.write_channels_to_file(&channel_ids, &platforms_map);
+    fn update_channel_platforms(&self, channel_id: u64, new_platforms: BTreeSet<String>) -> Result<bool, StoreError> {
+        self.store.set_platforms(channel_id, &new_platforms)
     }
 
-    fn get_ids_from_messages(messages: &Vec<Message>) -> Vec<u64> {
-        let mut result: Vec<u64> = vec![];
-        // Regex to find IDs in embed URLs like https://playstartrekonline.com/en/news/article/1234567
-        let re_embed_url = Regex::new(r"playstartrekonline\.com/en/news/article/(\d+)").unwrap();
-        // Original regex for message content (kept as fallback or for other potential ID formats)
-        let re_content = Regex::new(r"ID:(\d+)").unwrap(); // Adjusted to look for "ID:12345" pattern if needed, or keep original if that was intended. Let's assume URL is primary now.
-
-        for m in messages {
-            // Check embeds first
-            for embed in &m.embeds {
-                if let Some(url) = &embed.url {
-                    if let Some(capture) = re_embed_url.captures(url) {
-                        if let Ok(id) = capture[1].parse::<u64>() {
-                            result.push(id);
-                            // Assuming one news item per message, break after finding ID in embed
-                            break; 
-                        }
+    fn update_channel_categories(&self, channel_id: u64, new_categories: BTreeSet<String>) -> Result<bool, StoreError> {
+        self.store.set_categories(channel_id, &new_categories)
+    }
+
+    /// Run a `/stobot_filter` subcommand and return the reply.
+    fn handle_filter_command(&self, command: &CommandInteraction) -> String {
+        let channel_id = command.channel_id.get();
+        let mut filters = match self.store.get_subscription(channel_id) {
+            Ok(Some(subscription)) => subscription.filters,
+            Ok(None) => return "This channel is not registered. Use `/stobot_register` first.".to_string(),
+            Err(e) => {
+                log_error("Loading channel filters", e);
+                return "Could not load the filters, please try again later.".to_string();
+            }
+        };
+        let options = command.data.options();
+        let Some(ResolvedOption { name: subcommand, value: ResolvedValue::SubCommand(args), .. }) = options.first() else {
+            return "Missing filter subcommand".to_string();
+        };
+        let reply = match *subcommand {
+            "list" => {
+                return if filters.is_empty() {
+                    "This channel has no filter rules, every item matching its platforms and categories is posted.".to_string()
+                } else {
+                    let rules = filters.iter().enumerate()
+                        .map(|(i, rule)| format!("{}. {}", i + 1, rule))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    format!("**Filter rules for this channel**\n{}", rules)
+                };
+            },
+            "add" => {
+                let mut mode = None;
+                let mut pattern = "";
+                let mut is_regex = false;
+                for arg in args {
+                    match (arg.name, &arg.value) {
+                        ("mode", ResolvedValue::String(value)) => mode = FilterMode::from_name(value),
+                        ("pattern", ResolvedValue::String(value)) => pattern = value.trim(),
+                        ("regex", ResolvedValue::Boolean(value)) => is_regex = *value,
+                        _ => {},
                     }
                 }
-            }
-
-            // If not found in embed, check content (optional fallback)
-            if let Some(capture) = re_content.captures(m.content.as_str()) {
-                if let Ok(id) = capture[1].parse::<u64>() {
-                    result.push(id);
+                let Some(mode) = mode else {
+                    return "Mode must be `include` or `exclude`.".to_string();
+                };
+                if pattern.is_empty() {
+                    return "Pattern cannot be empty.".to_string();
                 }
+                let rule = FilterRule { mode, pattern: pattern.to_string(), is_regex };
+                if let Err(e) = rule.compile() {
+                    return format!("Invalid regex `{}`: {}", pattern, e);
+                }
+                if filters.contains(&rule) {
+                    return format!("This channel already has the rule: {}.", rule);
+                }
+                let reply = format!("Added filter rule: {}.", rule);
+                filters.push(rule);
+                reply
+            },
+            "remove" => {
+                let number = args.iter()
+                    .find_map(|arg| match (arg.name, &arg.value) {
+                        ("number", ResolvedValue::Integer(number)) => Some(*number),
+                        _ => None,
+                    })
+                    .unwrap_or(0);
+                if number < 1 || number as usize > filters.len() {
+                    return format!("There is no rule number {}, see `/stobot_filter list`.", number);
+                }
+                format!("Removed filter rule: {}.", filters.remove(number as usize - 1))
+            },
+            "clear" => {
+                filters.clear();
+                "Removed all filter rules for this channel.".to_string()
+            },
+            _ => return "Unknown filter subcommand".to_string(),
+        };
+        match self.store.set_filters(channel_id, &filters) {
+            Ok(true) => reply,
+            Ok(false) => "This channel is not registered. Use `/stobot_register` first.".to_string(),
+            Err(e) => {
+                log_error("Updating channel filters", e);
+                "Could not update the filters, please try again later.".to_string()
             }
         }
-        // Remove duplicates if necessary, although the logic should prevent adding the same ID twice per message
-        result.sort_unstable();
-        result.dedup();
-        result
     }
 
-    async fn fetch_and_filter_news(&self, tag: Option<&str>, limit: u32, platforms: &BTreeSet<String>) -> Option<News> {
-        let url = build_news_url(
-            tag,
-            Some(limit),
-            Some(0),
-            None,
-            &["images.img_microsite_thumbnail", "platforms", "updated"]
-        );
-        
-        match reqwest::get(&url).await {
-            Ok(resp) => match resp.text().await {
-                Ok(text) => {
-                    match serde_json::from_str::<crate::news::News>(&text) {
-                        Ok(mut news) => {
-                            if news.filter_news_by_platform(platforms) {
-                                Some(news)
-                            } else {
-                                None
-                            }
-                        },
-                        Err(why) => {
-                            log_error("Parsing news from API", why);
-                            None
-                        }
-                    }
+    /// Run a `/stobot_ping` subcommand and return the reply.
+    fn handle_ping_command(&self, command: &CommandInteraction) -> String {
+        let channel_id = command.channel_id.get();
+        let mut pings = match self.store.get_subscription(channel_id) {
+            Ok(Some(subscription)) => subscription.pings,
+            Ok(None) => return "This channel is not registered. Use `/stobot_register` first.".to_string(),
+            Err(e) => {
+                log_error("Loading channel pings", e);
+                return "Could not load the pings, please try again later.".to_string();
+            }
+        };
+        let options = command.data.options();
+        let Some(ResolvedOption { name: subcommand, value: ResolvedValue::SubCommand(args), .. }) = options.first() else {
+            return "Missing ping subcommand".to_string();
+        };
+        if *subcommand == "list" {
+            return if pings.is_empty() {
+                "Nobody is pinged for news in this channel.".to_string()
+            } else {
+                let rules = pings.iter().map(|rule| format!("• {}", rule)).collect::<Vec<_>>().join("\n");
+                format!("**Pings for this channel**\n{}", rules)
+            };
+        }
+
+        let mut role = None;
+        let mut here = false;
+        let mut categories = BTreeSet::new();
+        let mut platforms = BTreeSet::new();
+        for arg in args {
+            match (arg.name, &arg.value) {
+                ("role", ResolvedValue::Role(r)) => role = Some(r.id.get()),
+                ("here", ResolvedValue::Boolean(value)) => here = *value,
+                ("categories", ResolvedValue::String(value)) if !value.trim().is_empty() => match parse_category_list(value) {
+                    Ok(parsed) => categories = parsed,
+                    Err(e) => return e,
                 },
-                Err(why) => {
-                    log_error("Reading text from API response", why);
-                    None
+                ("platforms", ResolvedValue::String(value)) if !value.trim().is_empty() => match parse_platform_list(value) {
+                    Ok(parsed) => platforms = parsed,
+                    Err(e) => return e,
+                },
+                _ => {},
+            }
+        }
+        let target = match (role, here) {
+            (Some(_), true) | (None, false) => return "Choose either a role or `here`.".to_string(),
+            // The @everyone role shares its ID with the guild
+            (Some(id), false) if command.guild_id.is_some_and(|g| g.get() == id) => return "Pinging @everyone is not supported, use `here` instead.".to_string(),
+            (Some(id), false) => PingTarget::Role(id),
+            (None, true) => PingTarget::Here,
+        };
+
+        let reply = match *subcommand {
+            "add" => {
+                let rule = PingRule { target, categories, platforms };
+                let reply = format!("News posts in this channel will ping {}.", rule);
+                pings.retain(|p| p.target != target);
+                pings.push(rule);
+                reply
+            },
+            "remove" => {
+                let before = pings.len();
+                pings.retain(|p| p.target != target);
+                if pings.len() == before {
+                    return format!("{} is not pinged in this channel.", target.mention());
                 }
+                format!("{} will no longer be pinged in this channel.", target.mention())
             },
+            _ => return "Unknown ping subcommand".to_string(),
+        };
+        match self.store.set_pings(channel_id, &pings) {
+            Ok(true) => reply,
+            Ok(false) => "This channel is not registered. Use `/stobot_register` first.".to_string(),
+            Err(e) => {
+                log_error("Updating channel pings", e);
+                "Could not update the pings, please try again later.".to_string()
+            }
+        }
+    }
+
+    async fn fetch_news(&self, tag: Option<&str>, limit: u32) -> Option<News> {
+        match self.arc.latest_news(tag, limit).await {
+            Ok(news) => Some(news),
             Err(why) => {
                 log_error("Fetching news from API", why);
                 None
@@ -244,10 +298,47 @@ impl Handler {
         }
     }
 
+    async fn fetch_and_filter_news(&self, tag: Option<&str>, limit: u32, platforms: &BTreeSet<String>) -> Option<News> {
+        let mut news = self.fetch_news(tag, limit).await?;
+        if news.filter_news_by_platform(platforms) {
+            Some(news)
+        } else {
+            None
+        }
+    }
+
+    /// The `platforms` option of a command, or the channel's platforms if it was left out.
+    fn platforms_option(&self, command: &CommandInteraction) -> Result<BTreeSet<String>, String> {
+        match command.data.options.iter().find(|opt| opt.name == "platforms").and_then(|opt| opt.value.as_str()) {
+            Some(platforms_str) if !platforms_str.trim().is_empty() => parse_platform_list(platforms_str),
+            _ => Ok(self.get_channel_platforms(command.channel_id.get())),
+        }
+    }
+
+    async fn handle_autocomplete(&self, ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
+        let Some(focused) = command.data.autocomplete() else {
+            return Ok(());
+        };
+        let mut response = CreateAutocompleteResponse::new();
+        let choices = match (command.data.name.as_str(), focused.name) {
+            (_, "platforms") => complete_platform_list(focused.value),
+            (_, "categories") => complete_category_list(focused.value),
+            ("stobot_wiki", "query") => match self.wiki.complete(focused.value).await {
+                Ok(titles) => titles.into_iter().map(|title| (title.clone(), title)).collect(),
+                Err(e) => {
+                    log_error("Completing STOWiki titles", e);
+                    Vec::new()
+                }
+            },
+            _ => Vec::new(),
+        };
+        for (label, value) in choices {
+            response = response.add_string_choice(label, value);
+        }
+        command.create_response(&ctx.http, CreateInteractionResponse::Autocomplete(response)).await
+    }
+
     async fn register_commands(&self, ctx: &Context) {
-        // Remove all existing global commands before registering new ones
-        let _ = Command::set_global_commands(&ctx.http, vec![]);
-        
         // Define admin-only commands with permission restrictions
         let admin_commands = vec![
             CreateCommand::new("stobot_register")
@@ -262,6 +353,88 @@ impl Handler {
                 .add_option(
                     CreateCommandOption::new(CommandOptionType::String, "platforms", "Comma-separated platforms (pc,ps,xbox)")
                         .required(true)
+                        .set_autocomplete(true)
+                ),
+            CreateCommand::new("stobot_categories")
+                .description("Set which news categories are posted to this channel")
+                .default_member_permissions(serenity::model::permissions::Permissions::ADMINISTRATOR)
+                .add_option(
+                    CreateCommandOption::new(CommandOptionType::String, "categories", "Comma-separated categories (patch-notes,events,store,dev-blogs,other) or all")
+                        .required(true)
+                        .set_autocomplete(true)
+                ),
+            CreateCommand::new("stobot_filter")
+                .description("Manage keyword and regex filters for news posted to this channel")
+                .default_member_permissions(serenity::model::permissions::Permissions::ADMINISTRATOR)
+                .add_option(
+                    CreateCommandOption::new(CommandOptionType::SubCommand, "add", "Add a filter rule matched against title and summary")
+                        .add_sub_option(
+                            CreateCommandOption::new(CommandOptionType::String, "mode", "Only post matching items, or never post them")
+                                .required(true)
+                                .add_string_choice("include", "include")
+                                .add_string_choice("exclude", "exclude")
+                        )
+                        .add_sub_option(
+                            CreateCommandOption::new(CommandOptionType::String, "pattern", "Keyword, or regular expression if regex is set")
+                                .required(true)
+                        )
+                        .add_sub_option(
+                            CreateCommandOption::new(CommandOptionType::Boolean, "regex", "Treat the pattern as a regular expression (default: false)")
+                                .required(false)
+                        )
+                )
+                .add_option(
+                    CreateCommandOption::new(CommandOptionType::SubCommand, "remove", "Remove a filter rule")
+                        .add_sub_option(
+                            CreateCommandOption::new(CommandOptionType::Integer, "number", "Rule number as shown by /stobot_filter list")
+                                .required(true)
+                                .min_int_value(1)
+                        )
+                )
+                .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "list", "Show the filter rules of this channel"))
+                .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "clear", "Remove all filter rules of this channel")),
+            CreateCommand::new("stobot_ping")
+                .description("Mention a role or @here when news is posted to this channel")
+                .default_member_permissions(serenity::model::permissions::Permissions::ADMINISTRATOR)
+                .add_option(
+                    CreateCommandOption::new(CommandOptionType::SubCommand, "add", "Ping a role or @here for new posts")
+                        .add_sub_option(CreateCommandOption::new(CommandOptionType::Role, "role", "Role to mention").required(false))
+                        .add_sub_option(CreateCommandOption::new(CommandOptionType::Boolean, "here", "Mention @here instead of a role").required(false))
+                        .add_sub_option(
+                            CreateCommandOption::new(CommandOptionType::String, "categories", "Only ping for these categories (default: all)")
+                                .required(false)
+                                .set_autocomplete(true)
+                        )
+                        .add_sub_option(
+                            CreateCommandOption::new(CommandOptionType::String, "platforms", "Only ping for these platforms (default: all)")
+                                .required(false)
+                                .set_autocomplete(true)
+                        )
+                )
+                .add_option(
+                    CreateCommandOption::new(CommandOptionType::SubCommand, "remove", "Stop pinging a role or @here")
+                        .add_sub_option(CreateCommandOption::new(CommandOptionType::Role, "role", "Role to stop mentioning").required(false))
+                        .add_sub_option(CreateCommandOption::new(CommandOptionType::Boolean, "here", "Stop mentioning @here").required(false))
+                )
+                .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "list", "Show who is pinged in this channel")),
+            CreateCommand::new("stobot_postmode")
+                .description("Choose how news is posted to this channel")
+                .default_member_permissions(serenity::model::permissions::Permissions::ADMINISTRATOR)
+                .add_option(
+                    CreateCommandOption::new(CommandOptionType::String, "mode", "embed: one message of embeds; thread: a forum post or thread per item with the full article")
+                        .required(true)
+                        .add_string_choice("embed", "embed")
+                        .add_string_choice("thread", "thread")
+                ),
+            CreateCommand::new("stobot_updates")
+                .description("Choose what happens to posted news when ARC revises it")
+                .default_member_permissions(serenity::model::permissions::Permissions::ADMINISTRATOR)
+                .add_option(
+                    CreateCommandOption::new(CommandOptionType::String, "mode", "off: leave posts as they are; edit: show the current version; marker: also say what changed")
+                        .required(true)
+                        .add_string_choice("off", "off")
+                        .add_string_choice("edit", "edit")
+                        .add_string_choice("marker", "marker")
                 ),
             CreateCommand::new("stobot_status")
                 .description("Show current bot configuration")
@@ -277,6 +450,7 @@ impl Handler {
                 .add_option(
                     CreateCommandOption::new(CommandOptionType::String, "platforms", "Platforms to filter by (pc,ps,xbox). Default: use channel settings")
                         .required(false)
+                        .set_autocomplete(true)
                 )
                 .add_option(
                     CreateCommandOption::new(CommandOptionType::Integer, "weeks", "Number of weeks to look back (default: 1)")
@@ -289,6 +463,7 @@ impl Handler {
                 .add_option(
                     CreateCommandOption::new(CommandOptionType::String, "platforms", "Platforms to filter by (pc,ps,xbox). Default: use channel settings")
                         .required(false)
+                        .set_autocomplete(true)
                 )
                 .add_option(
                     CreateCommandOption::new(CommandOptionType::Integer, "weeks", "Number of weeks to look back (default: 1)")
@@ -296,17 +471,52 @@ impl Handler {
                         .min_int_value(1)
                         .max_int_value(52)
                 ),
-            CreateCommand::new("stobot_wiki")
-                .description("Search STOWiki.net for information (private reply)")
+            CreateCommand::new("stobot_search")
+                .description("Search older STO news by keyword")
                 .add_option(
-                    CreateCommandOption::new(CommandOptionType::String, "query", "Search term or article name")
+                    CreateCommandOption::new(CommandOptionType::String, "query", "Words to look for in the title or summary")
+                        .required(true)
+                )
+                .add_option(
+                    CreateCommandOption::new(CommandOptionType::String, "since", "Only items updated on or after this date (YYYY-MM-DD)")
+                        .required(false)
+                )
+                .add_option(
+                    CreateCommandOption::new(CommandOptionType::String, "until", "Only items updated on or before this date (YYYY-MM-DD)")
+                        .required(false)
+                )
+                .add_option(Platform::ALL.iter().fold(
+                    CreateCommandOption::new(CommandOptionType::String, "platform", "Only items for this platform").required(false),
+                    |option, p| option.add_string_choice(p.display_name(), p.api_name())
+                ))
+                .add_option(Category::ALL.iter().fold(
+                    CreateCommandOption::new(CommandOptionType::String, "category", "Only items in this category").required(false),
+                    |option, c| option.add_string_choice(c.display_name(), c.name())
+                )),
+            CreateCommand::new("stobot_article")
+                .description("Show the full text of a news article")
+                .add_option(
+                    CreateCommandOption::new(CommandOptionType::Integer, "id", "Article ID, the number at the end of the article link")
                         .required(true)
+                        .min_int_value(1)
+                )
+                .add_option(
+                    CreateCommandOption::new(CommandOptionType::Integer, "page", "Page to start at (default: 1)")
+                        .required(false)
+                        .min_int_value(1)
                 ),
-            CreateCommand::new("stobot_wiki_shared")
-                .description("Search STOWiki.net for information (shared in channel)")
+            CreateCommand::new("stobot_wiki")
+                .description("Search STOWiki.net for information")
                 .add_option(
                     CreateCommandOption::new(CommandOptionType::String, "query", "Search term or article name")
                         .required(true)
+                        .set_autocomplete(true)
+                )
+                .add_option(
+                    CreateCommandOption::new(CommandOptionType::String, "visibility", "private: only you see the answer (default); shared: post it to the channel")
+                        .required(false)
+                        .add_string_choice("private", "private")
+                        .add_string_choice("shared", "shared")
                 ),
         ];
         
@@ -323,120 +533,119 @@ impl Handler {
         }
     }
 
-    async fn get_and_show_news(&self, ctx: &Context, command: &CommandInteraction, tag: Option<&str>, title: &str, limit: u32, weeks: u32, exclude_tags: Option<Vec<&str>>, platforms: Option<BTreeSet<String>>) -> Result<(), serenity::Error> {
+    #[allow(clippy::too_many_arguments)]
+    async fn get_and_show_news(&self, ctx: &Context, command: &CommandInteraction, tag: Option<&str>, title: &str, limit: u32, weeks: u32, exclude_category: Option<Category>, platforms: Option<BTreeSet<String>>) -> Result<(), serenity::Error> {
+        // Fetching may retry with backoff, which easily takes longer than Discord waits for a reply
+        command.defer_ephemeral(&ctx.http).await?;
         let platforms = platforms.unwrap_or_else(|| self.get_channel_platforms(command.channel_id.get()));
         
         log_info("Fetching news", Some(&format!("Channel: {}, Tag: {:?}, Platforms: {:?}, Weeks: {}", command.channel_id.get(), tag, platforms, weeks)));
         
         // Use the helper function to fetch and filter news
-        match self.fetch_and_filter_news(tag, limit, &platforms).await {
+        let response = match self.fetch_and_filter_news(tag, limit, &platforms).await {
             Some(news) => {
                 let mut embeds = Vec::new();
                 let mut found_items = 0;
                 
                 // Create embeds for items within the specified time period
-                for item in news.iter().filter(|item| item.is_within_weeks(weeks) && exclude_tags.as_ref().map_or(true, |tags| !tags.contains(&item.get_tag()))) {
+                for item in news.iter().filter(|item| item.is_within_weeks(weeks) && exclude_category.is_none_or(|category| !item.categories().contains(&category))) {
                     found_items += 1;
-                    let (summary, icon_files) = item.format_with_platforms(&platforms);
-                    let mut embed = CreateEmbed::default()
-                        .title(item.get_title())
-                        .description(summary)
-                        .url(&format!("https://playstartrekonline.com/en/news/article/{}", item.get_id()));
-                    if let Some(img_url) = item.get_thumbnail_url() {
-                        embed = embed.thumbnail(img_url);
-                    }
-                    if let Some(icon_path) = icon_files.get(0) {
-                        embed = embed.image(format!("attachment://{}", icon_path.split('/').last().unwrap()));
-                    }
-                    embeds.push(embed);
+                    // Plain embeds, as turning pages edits the reply
+                    embeds.push(item.to_plain_embed(&platforms));
                     if found_items >= limit as usize {
                         break;
                     }
                 }
 
-                // When sending the response, add the icon files as attachments
                 if !embeds.is_empty() {
-                    let mut msg = CreateInteractionResponseMessage::new()
-                        .content(format!("{} Found {} {} from the last {} {} (Platforms: {:?})", 
-                            title, 
-                            found_items,
-                            if found_items == 1 { "item" } else { "items" },
+                    let header = format!("{} Found {} {} from the last {} {} (Platforms: {:?})", 
+                        title, 
+                        found_items,
+                        if found_items == 1 { "item" } else { "items" },
+                        weeks,
+                        if weeks == 1 { "week" } else { "weeks" },
+                        platforms);
+                    let pages = embeds.chunks(NEWS_PAGE_SIZE).map(|page| page.to_vec()).collect();
+                    self.pager.start(command.id.get(), header, pages, 0).followup()
+                } else {
+                    CreateInteractionResponseFollowup::new()
+                        .content(format!("No {} found from the last {} {} for platforms: {:?}", 
+                            match tag {
+                                Some("patch-notes") => "patch notes",
+                                Some("star-trek-online") => "news",
+                                _ => "announcements"
+                            }, 
                             weeks,
                             if weeks == 1 { "week" } else { "weeks" },
                             platforms))
-                        .embeds(embeds)
-                        .ephemeral(true);
-                    let mut all_icon_files = Vec::new();
-                    for item in news.iter() {
-                        let (_, icon_files) = item.format_with_platforms(&platforms);
-                        for icon in icon_files {
-                            if !all_icon_files.contains(&icon) {
-                                all_icon_files.push(icon);
-                            }
-                        }
-                    }
-                    for icon_path in all_icon_files {
-                        if let Ok(attachment) = CreateAttachment::path(icon_path.clone()).await {
-                            msg = msg.add_file(attachment);
-                        }
-                    }
-                    command.create_response(&ctx.http, CreateInteractionResponse::Message(msg)).await
-                } else {
-                    command.create_response(&ctx.http, CreateInteractionResponse::Message(
-                        CreateInteractionResponseMessage::new()
-                            .content(format!("No {} found from the last {} {} for platforms: {:?}", 
-                                match tag {
-                                    Some("patch-notes") => "patch notes",
-                                    Some("star-trek-online") => "news",
-                                    _ => "announcements"
-                                }, 
-                                weeks,
-                                if weeks == 1 { "week" } else { "weeks" },
-                                platforms))
-                            .ephemeral(true) // Makes this response only visible to the user who issued the command
-                    )).await
                 }
             },
             None => {
-                command.create_response(&ctx.http, CreateInteractionResponse::Message(
-                    CreateInteractionResponseMessage::new()
-                        .content(format!("No {} found for platforms: {:?}", 
-                            if tag.is_some() { "news" } else { "announcements" }, 
-                            platforms))
-                        .ephemeral(true) // Makes this response only visible to the user who issued the command
-                )).await
+                CreateInteractionResponseFollowup::new()
+                    .content(format!("No {} found for platforms: {:?}", 
+                        if tag.is_some() { "news" } else { "announcements" }, 
+                        platforms))
             }
-        }
+        };
+        // Makes this response only visible to the user who issued the command
+        command.create_followup(&ctx.http, response.ephemeral(true)).await?;
+        Ok(())
     }
 
     async fn handle_slash_command(&self, ctx: &Context, command: &CommandInteraction) -> Result<(), serenity::Error> {
         let content = match command.data.name.as_str() {
             "stobot_register" => {
                 let id = command.channel_id.get();
-                self.add_channel(id);
-                log_info("Registered channel", Some(&format!("ID:{}", id)));
-                format!("This channel (ID: {}) will now have STO news posted.", id)
+                match self.add_channel(id, command.guild_id.map(|g| g.get())) {
+                    Ok(true) => {
+                        log_info("Registered channel", Some(&format!("ID:{}", id)));
+                        format!("This channel (ID: {}) will now have STO news posted.", id)
+                    },
+                    Ok(false) => format!("This channel (ID: {}) is already registered, its settings were kept.", id),
+                    Err(e) => {
+                        log_error("Registering channel", e);
+                        "Could not register this channel, please try again later.".to_string()
+                    }
+                }
             },
             "stobot_unregister" => {
                 let id = command.channel_id.get();
-                self.remove_channel(id);
-                log_info("Removed channel", Some(&format!("ID:{}", id)));
-                format!("This channel (ID: {}) will no longer have STO news posted.", id)
+                match self.remove_channel(id) {
+                    Ok(true) => {
+                        log_info("Removed channel", Some(&format!("ID:{}", id)));
+                        format!("This channel (ID: {}) will no longer have STO news posted.", id)
+                    },
+                    Ok(false) => format!("This channel (ID: {}) is not registered.", id),
+                    Err(e) => {
+                        log_error("Unregistering channel", e);
+                        "Could not unregister this channel, please try again later.".to_string()
+                    }
+                }
             },
             "stobot_status" => {
                 let channel_id = command.channel_id.get();
                 let platforms = self.get_channel_platforms(channel_id);
-                let is_registered = self.channel_ids.lock().unwrap().contains(&channel_id);
+                let subscription = self.store.get_subscription(channel_id).ok().flatten();
+                let categories = subscription.as_ref().map(|s| s.categories.clone()).unwrap_or_default();
+                let filter_count = subscription.as_ref().map_or(0, |s| s.filters.len());
+                let ping_count = subscription.as_ref().map_or(0, |s| s.pings.len());
+                let post_mode = subscription.as_ref().map_or(PostMode::Embed, PostMode::of);
+                let update_mode = subscription.as_ref().map_or(UpdateMode::Edit, UpdateMode::of);
+                let is_registered = self.is_registered(channel_id);
                 
+                let polling = format!(
+                    "• Polling Period: {} seconds (set via environment variable)\n• Poll Cycles: {} ({} API requests, {} saved by sharing the feed, {} poller restarts)",
+                    self.poll_period, self.poll_metrics.cycles(), self.poll_metrics.upstream_requests(), self.poll_metrics.requests_saved(), self.poll_metrics.restarts()
+                );
                 if is_registered {
                     format!(
-                        "📊 **Bot Status**\n• Polling Period: {} seconds (set via environment variable)\n• This Channel's Platforms: {:?}\n• This Channel: Registered",
-                        self.poll_period, platforms
+                        "📊 **Bot Status**\n{}\n• This Channel's Platforms: {:?}\n• This Channel's Categories: {}\n• This Channel's Filter Rules: {}\n• This Channel's Pings: {}\n• This Channel's Post Mode: {}\n• This Channel's Revision Updates: {}\n• This Channel: Registered",
+                        polling, platforms, describe_categories(&categories), filter_count, ping_count, post_mode.name(), update_mode.name()
                     )
                 } else {
                     format!(
-                        "📊 **Bot Status**\n• Polling Period: {} seconds (set via environment variable)\n• This Channel: Not Registered\n• Use `/stobot_register` to register this channel",
-                        self.poll_period
+                        "📊 **Bot Status**\n{}\n• This Channel: Not Registered\n• Use `/stobot_register` to register this channel",
+                        polling
                     )
                 }
             },
@@ -446,27 +655,35 @@ impl Handler {
                 • `/stobot_register` - Register this channel for STO news\n\
                 • `/stobot_unregister` - Unregister this channel\n\
                 • `/stobot_status` - Show current configuration\n\
-                • `/stobot_setplatforms <platforms>` - Set monitored platforms (comma-separated, e.g., pc,ps,xbox)\n\n\
+                • `/stobot_setplatforms <platforms>` - Set monitored platforms (comma-separated, e.g., pc,ps,xbox; aliases like playstation or xb1 work too)\n\
+                • `/stobot_categories <categories>` - Set posted news categories (comma-separated, e.g., patch-notes,events; or all)\n\
+                • `/stobot_filter add|remove|list|clear` - Only post, or never post, items whose title or summary matches a keyword or regex\n\
+                • `/stobot_ping add|remove|list` - Mention a role or @here on new posts, optionally only for some categories or platforms\n\
+                • `/stobot_postmode <embed|thread>` - Post news as embeds, or as a forum post or thread per item with the full article\n\
+                • `/stobot_updates <off|edit|marker>` - Edit posted news when ARC revises it, optionally noting what changed\n\n\
                 **General Commands**:\n\
                 • `/stobot_news [platforms](Defaults to all Platforms) [weeks](Defaults to 1 Week)` - Show recent STO news (excluding patch notes)\n\
                 • `/stobot_patchnotes [platforms](Defaults to all Platforms) [weeks](Defaults to 1 Week)` - Show recent STO patch notes\n\
-                • `/stobot_wiki <query>` - Search STOWiki.net for information (private reply)\n\
-                • `/stobot_wiki_shared <query>` - Search STOWiki.net for information (shared in channel)\n\
+                • `/stobot_search <query> [since] [until] [platform] [category]` - Search older STO news by keyword\n\
+                • `/stobot_article <id> [page]` - Show the full text of a news article\n\
+                • `/stobot_wiki <query> [visibility]` - Search STOWiki.net for information, privately or shared in the channel\n\
                 • `/stobot_help` - Show this help message".to_string()
             },
             "stobot_setplatforms" => {
                 let channel_id = command.channel_id.get();
                 let options = &command.data.options;
-                if let Some(option) = options.get(0) {
+                if let Some(option) = options.first() {
                     if let Some(platforms_str) = option.value.as_str() {
-                        let platforms: Vec<String> = platforms_str.split(',').map(|s| s.trim().to_string()).collect();
-                        let platform_set: BTreeSet<String> = platforms.into_iter().collect();
-                        
-                        if platform_set.is_empty() {
-                            "Platform list cannot be empty.".to_string()
-                        } else {
-                            self.update_channel_platforms(channel_id, platform_set.clone());
-                            format!("Monitored platforms for this channel updated to {:?}.", platform_set)
+                        match parse_platform_list(platforms_str) {
+                            Err(e) => e,
+                            Ok(platform_set) => match self.update_channel_platforms(channel_id, platform_set.clone()) {
+                                Ok(true) => format!("Monitored platforms for this channel updated to {:?}.", platform_set),
+                                Ok(false) => "This channel is not registered. Use `/stobot_register` first.".to_string(),
+                                Err(e) => {
+                                    log_error("Updating channel platforms", e);
+                                    "Could not update the platforms, please try again later.".to_string()
+                                }
+                            }
                         }
                     } else {
                         "Invalid platforms value provided".to_string()
@@ -475,26 +692,62 @@ impl Handler {
                     "Missing platforms parameter".to_string()
                 }
             },
-            "stobot_patchnotes" => {
-                // Parse platforms parameter if provided
-                let platforms = if let Some(platform_option) = command.data.options.iter().find(|opt| opt.name == "platforms") {
-                    if let Some(platforms_str) = platform_option.value.as_str() {
-                        let platform_set: BTreeSet<String> = platforms_str
-                            .split(',')
-                            .map(|s| s.trim().to_string())
-                            .filter(|s| !s.is_empty())
-                            .collect();
-                        
-                        if !platform_set.is_empty() {
-                            platform_set
-                        } else {
-                            self.get_channel_platforms(command.channel_id.get())
+            "stobot_categories" => {
+                let channel_id = command.channel_id.get();
+                match command.data.options.first().and_then(|opt| opt.value.as_str()) {
+                    Some(categories_str) => match parse_category_list(categories_str) {
+                        Err(e) => e,
+                        Ok(category_set) => match self.update_channel_categories(channel_id, category_set.clone()) {
+                            Ok(true) => format!("News categories for this channel updated to {}.", describe_categories(&category_set)),
+                            Ok(false) => "This channel is not registered. Use `/stobot_register` first.".to_string(),
+                            Err(e) => {
+                                log_error("Updating channel categories", e);
+                                "Could not update the categories, please try again later.".to_string()
+                            }
                         }
-                    } else {
-                        self.get_channel_platforms(command.channel_id.get())
+                    },
+                    None => "Missing categories parameter".to_string(),
+                }
+            },
+            "stobot_filter" => self.handle_filter_command(command),
+            "stobot_ping" => self.handle_ping_command(command),
+            "stobot_postmode" => {
+                let channel_id = command.channel_id.get();
+                match command.data.options.first().and_then(|opt| opt.value.as_str()).and_then(PostMode::from_name) {
+                    Some(mode) => match self.store.set_setting(channel_id, POST_MODE_SETTING, Some(mode.name())) {
+                        Ok(true) => format!("News in this channel will now be posted in `{}` mode.", mode.name()),
+                        Ok(false) => "This channel is not registered. Use `/stobot_register` first.".to_string(),
+                        Err(e) => {
+                            log_error("Updating channel post mode", e);
+                            "Could not update the post mode, please try again later.".to_string()
+                        }
+                    },
+                    None => "Mode must be `embed` or `thread`.".to_string(),
+                }
+            },
+            "stobot_updates" => {
+                let channel_id = command.channel_id.get();
+                match command.data.options.first().and_then(|opt| opt.value.as_str()).and_then(UpdateMode::from_name) {
+                    Some(mode) => match self.store.set_setting(channel_id, UPDATE_MODE_SETTING, Some(mode.name())) {
+                        Ok(true) => format!("Revised news in this channel will now be handled in `{}` mode.", mode.name()),
+                        Ok(false) => "This channel is not registered. Use `/stobot_register` first.".to_string(),
+                        Err(e) => {
+                            log_error("Updating channel revision mode", e);
+                            "Could not update the revision mode, please try again later.".to_string()
+                        }
+                    },
+                    None => "Mode must be `off`, `edit` or `marker`.".to_string(),
+                }
+            },
+            "stobot_patchnotes" => {
+                let platforms = match self.platforms_option(command) {
+                    Ok(platforms) => platforms,
+                    Err(e) => {
+                        command.create_response(&ctx.http, CreateInteractionResponse::Message(
+                            CreateInteractionResponseMessage::new().content(e).ephemeral(true)
+                        )).await?;
+                        return Ok(());
                     }
-                } else {
-                    self.get_channel_platforms(command.channel_id.get())
                 };
                 
                 // Get the weeks parameter or default to 1
@@ -507,25 +760,14 @@ impl Handler {
                 return Ok(());
             },
             "stobot_news" => {
-                // Parse platforms parameter if provided
-                let platforms = if let Some(platform_option) = command.data.options.iter().find(|opt| opt.name == "platforms") {
-                    if let Some(platforms_str) = platform_option.value.as_str() {
-                        let platform_set: BTreeSet<String> = platforms_str
-                            .split(',')
-                            .map(|s| s.trim().to_string())
-                            .filter(|s| !s.is_empty())
-                            .collect();
-                        
-                        if !platform_set.is_empty() {
-                            platform_set
-                        } else {
-                            self.get_channel_platforms(command.channel_id.get())
-                        }
-                    } else {
-                        self.get_channel_platforms(command.channel_id.get())
+                let platforms = match self.platforms_option(command) {
+                    Ok(platforms) => platforms,
+                    Err(e) => {
+                        command.create_response(&ctx.http, CreateInteractionResponse::Message(
+                            CreateInteractionResponseMessage::new().content(e).ephemeral(true)
+                        )).await?;
+                        return Ok(());
                     }
-                } else {
-                    self.get_channel_platforms(command.channel_id.get())
                 };
                 
                 // Get the weeks parameter or default to 1
@@ -534,25 +776,37 @@ impl Handler {
                     .and_then(|opt| opt.value.as_i64())
                     .unwrap_or(1) as u32;
                 
-                self.get_and_show_news(ctx, command, Some("star-trek-online"), &format!("**STO News (last {} {})**:", weeks, if weeks == 1 { "week" } else { "weeks" }), 20, weeks, Some(vec!["patch-notes"]), Some(platforms)).await?;
+                self.get_and_show_news(ctx, command, Some("star-trek-online"), &format!("**STO News (last {} {})**:", weeks, if weeks == 1 { "week" } else { "weeks" }), 20, weeks, Some(Category::PatchNotes), Some(platforms)).await?;
                 return Ok(());
             },
-            "stobot_wiki" => {
-                let options = command.data.options.get(0);
-                let query = options
-                    .and_then(|opt| opt.value.as_str())
-                    .unwrap_or("");
-                self.handle_wiki_search(ctx, command, query).await?;
-                return Ok(());
+            "stobot_search" => {
+                let option = |name: &str| command.data.options.iter().find(|opt| opt.name == name).and_then(|opt| opt.value.as_str());
+                match SearchQuery::parse(option("query").unwrap_or(""), option("since"), option("until"), option("platform"), option("category")) {
+                    Ok(query) => {
+                        self.handle_search(ctx, command, &query).await?;
+                        return Ok(());
+                    },
+                    Err(e) => e,
+                }
             },
-            "stobot_wiki_shared" => {
-                let options = command.data.options.get(0);
-                let query = options
-                    .and_then(|opt| opt.value.as_str())
-                    .unwrap_or("");
-                self.handle_wiki_search_shared(ctx, command, query).await?;
+            "stobot_article" => {
+                let option = |name: &str| command.data.options.iter().find(|opt| opt.name == name).and_then(|opt| opt.value.as_i64());
+                let id = option("id").unwrap_or(0).max(0) as u64;
+                let page = option("page").unwrap_or(1).max(1) as usize;
+                self.handle_article(ctx, command, id, page).await?;
                 return Ok(());
             },
+            "stobot_wiki" => {
+                let option = |name: &str| command.data.options.iter().find(|opt| opt.name == name).and_then(|opt| opt.value.as_str());
+                let query = option("query").unwrap_or("").trim();
+                if query.is_empty() {
+                    "Please provide a search term for the STOWiki.".to_string()
+                } else {
+                    let visibility = option("visibility").and_then(WikiVisibility::from_name).unwrap_or_default();
+                    self.handle_wiki(ctx, command, query, visibility).await?;
+                    return Ok(());
+                }
+            },
             _ => "Unknown command".to_string(),
         };
 
@@ -565,139 +819,77 @@ impl Handler {
             .await
     }
 
-    async fn handle_wiki_search(&self, ctx: &Context, command: &CommandInteraction, query: &str) -> Result<(), serenity::Error> {
-        command.defer(&ctx.http).await?;
-        
-        if query.is_empty() {
-            command.create_followup(&ctx.http, CreateInteractionResponseFollowup::new()
-                .content("Please provide a search term for the STOWiki.")
-                .ephemeral(true)).await?;
-            return Ok(());
-        }
-        
-        // Build the STOWiki search URL
-        let search_url = format!("https://stowiki.net/wiki/Special:Search?search={}&go=Go", 
-            query.replace(" ", "%20"));
-        
-        // For direct article URL (if query matches article exactly)
-        let direct_article_url = format!("https://stowiki.net/wiki/{}", 
-            query.replace(" ", "_"));
-        
-        // Try to fetch the article and extract a preview
-        let preview = match reqwest::get(&direct_article_url).await {
-            Ok(resp) => {
-                if resp.status().is_success() {
-                    if let Ok(body) = resp.text().await {
-                        let document = Html::parse_document(&body);
-                        let selector = Selector::parse("#mw-content-text > div.mw-parser-output > p").unwrap();
-                        if let Some(element) = document.select(&selector).next() {
-                            let text = element.text().collect::<Vec<_>>().join("").trim().to_string();
-                            if !text.is_empty() {
-                                Some(text)
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            },
-            Err(_) => None,
+    async fn handle_search(&self, ctx: &Context, command: &CommandInteraction, query: &SearchQuery) -> Result<(), serenity::Error> {
+        command.defer_ephemeral(&ctx.http).await?;
+        log_info("Searching news", Some(&format!("Channel: {}, Query: {:?}", command.channel_id.get(), query)));
+        let results = match search_news(&self.arc, self.store.as_ref(), query).await {
+            Ok(results) => results,
+            Err(e) => {
+                log_error("Searching news", e);
+                command.create_followup(&ctx.http, CreateInteractionResponseFollowup::new()
+                    .content("Could not search the news right now, please try again later.")
+                    .ephemeral(true)).await?;
+                return Ok(());
+            }
         };
-        
-        // Create the response with relevant links and preview
-        let description = match &preview {
-            Some(preview) => format!(
-                "[View search results]({}) | [Try direct article link]({})\n\n{}\n\nSTOWiki.net is the community Star Trek Online wiki.",
-                search_url, direct_article_url, preview
-            ),
-            None => format!(
-                "[View search results]({}) | [Try direct article link]({})\n\nSTOWiki.net is the community Star Trek Online wiki.",
-                search_url, direct_article_url
-            ),
+        let terms = query.terms.join(" ");
+        let response = if results.is_empty() {
+            CreateInteractionResponseFollowup::new()
+                .content(format!("No news found for `{}`.", terms))
+        } else {
+            let all_platforms = Platform::ALL.iter().map(|p| p.api_name().to_string()).collect();
+            let embeds: Vec<CreateEmbed> = results.iter().map(|item| item.to_plain_embed(&all_platforms)).collect();
+            let header = format!("**STO News Search** Found {} {} for `{}`",
+                results.len(), if results.len() == 1 { "item" } else { "items" }, terms);
+            let pages = embeds.chunks(NEWS_PAGE_SIZE).map(|page| page.to_vec()).collect();
+            self.pager.start(command.id.get(), header, pages, 0).followup()
         };
-        
-        let response = CreateInteractionResponseFollowup::new()
-            .embed(CreateEmbed::new()
-                .title(format!("STOWiki Search: {}", query))
-                .description(description)
-                .color(0x00ADEF) // STO blue color
-                .footer(CreateEmbedFooter::new("Results from STOWiki.net")));
-        
-        command.create_followup(&ctx.http, response).await?;
+        command.create_followup(&ctx.http, response.ephemeral(true)).await?;
         Ok(())
     }
 
-    async fn handle_wiki_search_shared(&self, ctx: &Context, command: &CommandInteraction, query: &str) -> Result<(), serenity::Error> {
-        command.defer(&ctx.http).await?;
-        
-        if query.is_empty() {
-            command.create_followup(&ctx.http, CreateInteractionResponseFollowup::new()
-                .content("Please provide a search term for the STOWiki.")
-                .ephemeral(true)).await?;
-            return Ok(());
-        }
-        
-        // Build the STOWiki search URL
-        let search_url = format!("https://stowiki.net/wiki/Special:Search?search={}&go=Go", 
-            query.replace(" ", "%20"));
-        
-        // For direct article URL (if query matches article exactly)
-        let direct_article_url = format!("https://stowiki.net/wiki/{}", 
-            query.replace(" ", "_"));
-        
-        // Try to fetch the article and extract a preview
-        let preview = match reqwest::get(&direct_article_url).await {
-            Ok(resp) => {
-                if resp.status().is_success() {
-                    if let Ok(body) = resp.text().await {
-                        let document = Html::parse_document(&body);
-                        let selector = Selector::parse("#mw-content-text > div.mw-parser-output > p").unwrap();
-                        if let Some(element) = document.select(&selector).next() {
-                            let text = element.text().collect::<Vec<_>>().join("").trim().to_string();
-                            if !text.is_empty() {
-                                Some(text)
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            },
-            Err(_) => None,
+    /// Reply with one page of an article's full text, `page` counted from one.
+    async fn handle_article(&self, ctx: &Context, command: &CommandInteraction, id: u64, page: usize) -> Result<(), serenity::Error> {
+        command.defer_ephemeral(&ctx.http).await?;
+        let article = match self.arc.fetch_article(id).await {
+            Ok(article) => article,
+            Err(e) => {
+                log_error(&format!("Fetching article {}", id), e);
+                command.create_followup(&ctx.http, CreateInteractionResponseFollowup::new()
+                    .content(format!("Could not load article {}.", id))
+                    .ephemeral(true)).await?;
+                return Ok(());
+            }
         };
-        
-        // Create the response with relevant links and preview
-        let description = match &preview {
-            Some(preview) => format!(
-                "[View search results]({}) | [Try direct article link]({})\n\n{}\n\nSTOWiki.net is the community Star Trek Online wiki.",
-                search_url, direct_article_url, preview
-            ),
-            None => format!(
-                "[View search results]({}) | [Try direct article link]({})\n\nSTOWiki.net is the community Star Trek Online wiki.",
-                search_url, direct_article_url
-            ),
+        let pages = article.pages();
+        let response = if pages.is_empty() {
+            CreateInteractionResponseFollowup::new().content(format!("Article {} has no text.", id))
+        } else {
+            let embeds = pages.iter().map(|text| vec![article.page_embed(text)]).collect();
+            self.pager.start(command.id.get(), String::new(), embeds, page - 1).followup()
         };
-        
-        let response = CreateInteractionResponseFollowup::new()
-            .embed(CreateEmbed::new()
-                .title(format!("STOWiki Search: {}", query))
-                .description(description)
-                .color(0x00ADEF) // STO blue color
-                .footer(CreateEmbedFooter::new("Results from STOWiki.net")));
-        
-        command.create_followup(&ctx.http, response).await?;
+        command.create_followup(&ctx.http, response.ephemeral(true)).await?;
+        Ok(())
+    }
+
+    /// Reply with what STOWiki has on `query`, only to the caller unless the answer is shared.
+    async fn handle_wiki(&self, ctx: &Context, command: &CommandInteraction, query: &str, visibility: WikiVisibility) -> Result<(), serenity::Error> {
+        let private = visibility == WikiVisibility::Private;
+        if private {
+            command.defer_ephemeral(&ctx.http).await?;
+        } else {
+            command.defer(&ctx.http).await?;
+        }
+        log_info("Looking up STOWiki", Some(&format!("Channel: {}, Query: {:?}, Visibility: {}", command.channel_id.get(), query, visibility.name())));
+        let response = match self.wiki.lookup(query).await {
+            Ok(result) => CreateInteractionResponseFollowup::new().embed(result.to_embed()),
+            Err(e) => {
+                log_error(&format!("Looking up {:?} on STOWiki", query), e);
+                CreateInteractionResponseFollowup::new()
+                    .content(format!("Could not reach STOWiki right now, try [searching it directly]({}).", self.wiki.search_url(query)))
+            }
+        };
+        command.create_followup(&ctx.http, response.ephemeral(private)).await?;
         Ok(())
     }
 }
@@ -712,65 +904,35 @@ impl EventHandler for Handler {
     async fn ready(&self, ctx: Context, ready: Ready) {
         log_info("Bot connected", Some(&format!("Connected as {} ({})", ready.user.name, ready.user.id)));
         
-        // Register slash commands
+        // Register slash commands. This runs again on every reconnect, the news poller runs independently in its own task.
         self.register_commands(&ctx).await;
-
-        loop {
-            for channel_id in self.get_channels().iter() {
-                let channel_platforms = self.get_channel_platforms(*channel_id);
-                if let Some(news) = self.fetch_and_filter_news(None, self.poll_count as u32, &channel_platforms).await {
-                    let channel = ChannelId::new(*channel_id);
-                    let builder = GetMessages::new().limit(self.msg_count);
-                    if let Ok(existing_messages) = channel.messages(&ctx.http, builder).await {
-                        let existing_ids = Self::get_ids_from_messages(&existing_messages);
-                        let mut embeds = Vec::new();
-                        let mut embed_icon_files = Vec::new();
-                        for item in news.iter() {
-                            if !existing_ids.contains(&item.get_id()) && item.is_fresh(self.fresh_seconds) {
-                                log_info("Sending news", Some(&format!("ID:{} Channel:{} Platforms:{:?}", item.get_id(), *channel_id, channel_platforms)));
-                                let (summary, icon_files) = item.format_with_platforms(&channel_platforms);
-                                let mut embed = CreateEmbed::default()
-                                    .title(item.get_title())
-                                    .url(&format!("https://playstartrekonline.com/en/news/article/{}", item.get_id()))
-                                    .description(summary);
-                                if let Some(img_url) = item.get_thumbnail_url() {
-                                    embed = embed.thumbnail(img_url);
-                                }
-                                // Attach the first platform icon as the embed image (if any)
-                                if let Some(icon_path) = icon_files.get(0) {
-                                    let filename = icon_path.split('/').last().unwrap();
-                                    embed = embed.image(format!("attachment://{}", filename));
-                                    embed_icon_files.push(icon_path.clone());
-                                } else {
-                                    embed_icon_files.push(String::new());
-                                }
-                                embeds.push(embed);
-                            }
-                        }
-                        if !embeds.is_empty() {
-                            let mut msg = serenity::builder::CreateMessage::default().embeds(embeds);
-                            for icon_path in embed_icon_files.iter().filter(|p| !p.is_empty()) {
-                                if let Ok(attachment) = CreateAttachment::path(icon_path.clone()).await {
-                                    msg = msg.add_file(attachment);
-                                }
-                            }
-                            match channel.send_message(&ctx.http, msg).await {
-                                Ok(_) => {},
-                                Err(e) => log_error("Failed to send scheduled news message", e),
-                            }
-                        }
-                    }
-                }
-            }
-            time::sleep(Duration::from_secs(self.poll_period)).await;
-        }
     }
 
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
-        if let Interaction::Command(command) = interaction {
-            if let Err(why) = self.handle_slash_command(&ctx, &command).await {
-                log_error("Processing slash command interaction", why);
-            }
+        match interaction {
+            Interaction::Command(command) => {
+                if let Err(why) = self.handle_slash_command(&ctx, &command).await {
+                    log_error("Processing slash command interaction", why);
+                }
+            },
+            Interaction::Autocomplete(command) => {
+                if let Err(why) = self.handle_autocomplete(&ctx, &command).await {
+                    log_error("Processing autocomplete interaction", why);
+                }
+            },
+            Interaction::Component(component) => {
+                if let Some(response) = self.pager.handle_component(&component.data.custom_id)
+                    && let Err(why) = component.create_response(&ctx.http, response).await {
+                    log_error("Processing component interaction", why);
+                }
+            },
+            Interaction::Modal(modal) => {
+                if let Some(response) = self.pager.handle_modal(&modal.data.custom_id, &modal.data.components)
+                    && let Err(why) = modal.create_response(&ctx.http, response).await {
+                    log_error("Processing modal interaction", why);
+                }
+            },
+            _ => {},
         }
     }
 }
@@ -0,0 +1,231 @@
+// Extraction of STOWiki infoboxes, the stat tables of starships, traits, consoles and equipment
+use scraper::{ElementRef, Html, Node, Selector};
+use serenity::builder::CreateEmbed;
+
+use crate::markdown::truncate;
+
+/// Longest value shown per field.
+const VALUE_LIMIT: usize = 200;
+
+/// Longest value shown side by side with others.
+const INLINE_LIMIT: usize = 40;
+
+/// Most fields shown for infoboxes without a fixed layout.
+const MAX_GENERIC_FIELDS: usize = 12;
+
+/// Elements STOWiki and MediaWiki skins render infobox templates as.
+const INFOBOX_SELECTOR: &str = "table.infobox, div.infobox, aside.portable-infobox, table[class*='infobox'], div[class*='infobox']";
+
+/// What an infobox describes, which decides the fields shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InfoboxKind {
+    Starship,
+    Trait,
+    Console,
+    Equipment,
+}
+
+/// Fields shown for starships, in order, each with the labels STOWiki uses for it.
+const STARSHIP_FIELDS: &[(&str, &[&str])] = &[
+    ("Tier", &["tier"]),
+    ("Faction", &["faction", "factions"]),
+    ("Hull", &["hull", "hull strength", "base hull"]),
+    ("Shields", &["shield modifier", "shields", "shield"]),
+    ("Bridge Officers", &["bridge officers", "bridge officer seating", "boff seating", "boffs"]),
+    ("Weapons", &["weapons", "fore weapons", "aft weapons"]),
+    ("Consoles", &["consoles", "engineering consoles", "science consoles", "tactical consoles", "universal consoles"]),
+    ("Ship Trait", &["starship trait", "ship trait", "trait"]),
+];
+
+/// The rows of an infobox, and the image it shows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Infobox {
+    pub kind: InfoboxKind,
+    /// `(label, value)` pairs as shown on the page
+    pub rows: Vec<(String, String)>,
+    /// Absolute URL of the infobox image
+    pub image: Option<String>,
+}
+
+impl Infobox {
+    /// The first infobox in rendered page HTML, with image links made absolute against `base_url`.
+    pub fn extract(html: &str, base_url: &str) -> Option<Infobox> {
+        let document = Html::parse_fragment(html);
+        let infobox_selector = Selector::parse(INFOBOX_SELECTOR).unwrap();
+        let infobox = document.select(&infobox_selector).next()?;
+        let rows = rows(infobox);
+        if rows.is_empty() {
+            return None;
+        }
+        let image_selector = Selector::parse("img").unwrap();
+        let image = infobox.select(&image_selector)
+            .filter_map(|img| img.value().attr("src"))
+            .find(|src| !src.ends_with(".svg"))
+            .map(|src| absolute_url(src, base_url));
+        let classes = infobox.value().attr("class").unwrap_or_default().to_lowercase();
+        Some(Infobox { kind: kind(&classes, &rows), rows, image })
+    }
+
+    /// The value of the first row with one of `labels`, compared without case.
+    fn value(&self, labels: &[&str]) -> Option<&str> {
+        labels.iter().find_map(|label| self.rows.iter().find(|(l, _)| normalize(l) == *label).map(|(_, v)| v.as_str()))
+    }
+
+    /// `(name, value)` of the embed fields to show, in order.
+    pub fn fields(&self) -> Vec<(String, String)> {
+        if self.kind != InfoboxKind::Starship {
+            return self.rows.iter()
+                .take(MAX_GENERIC_FIELDS)
+                .map(|(label, value)| (label.clone(), truncate(value, VALUE_LIMIT)))
+                .collect();
+        }
+        STARSHIP_FIELDS.iter()
+            .filter_map(|(name, labels)| {
+                // Slots are often split over several rows, show them together
+                let value = match *name {
+                    "Weapons" | "Consoles" => {
+                        let parts: Vec<String> = labels.iter()
+                            .filter_map(|label| self.rows.iter().find(|(l, _)| normalize(l) == *label))
+                            .map(|(l, v)| if normalize(l) == labels[0] { v.clone() } else { format!("{}: {}", l, v) })
+                            .collect();
+                        if parts.is_empty() { None } else { Some(parts.join("\n")) }
+                    },
+                    _ => self.value(labels).map(str::to_string),
+                }?;
+                Some((name.to_string(), truncate(&value, VALUE_LIMIT)))
+            })
+            .collect()
+    }
+
+    /// Add the fields to `embed`, short ones side by side.
+    pub fn apply(&self, mut embed: CreateEmbed) -> CreateEmbed {
+        for (name, value) in self.fields() {
+            let inline = !value.contains('\n') && value.chars().count() <= INLINE_LIMIT;
+            embed = embed.field(name, value, inline);
+        }
+        embed
+    }
+}
+
+fn kind(classes: &str, rows: &[(String, String)]) -> InfoboxKind {
+    let type_value = rows.iter()
+        .find(|(label, _)| normalize(label) == "type")
+        .map(|(_, value)| value.to_lowercase())
+        .unwrap_or_default();
+    let has = |label: &str| rows.iter().any(|(l, _)| normalize(l).contains(label));
+    if classes.contains("ship") || has("hull") || has("bridge officer") {
+        InfoboxKind::Starship
+    } else if classes.contains("trait") || type_value.contains("trait") {
+        InfoboxKind::Trait
+    } else if classes.contains("console") || type_value.contains("console") {
+        InfoboxKind::Console
+    } else {
+        InfoboxKind::Equipment
+    }
+}
+
+/// Label and value of every row, from `th`/`td` or `td`/`td` table rows and portable infobox data items.
+fn rows(infobox: ElementRef) -> Vec<(String, String)> {
+    let row_selector = Selector::parse("tr, .pi-data").unwrap();
+    let cell_selector = Selector::parse("th, td, .pi-data-label, .pi-data-value").unwrap();
+    infobox.select(&row_selector)
+        .filter_map(|row| {
+            let cells: Vec<ElementRef> = row.select(&cell_selector).collect();
+            let [label, value] = cells.as_slice() else {
+                return None;
+            };
+            let label = cell_text(*label).replace('\n', " ");
+            let label = label.trim_end_matches(':').trim().to_string();
+            let value = cell_text(*value);
+            (!label.is_empty() && !value.is_empty()).then_some((label, value))
+        })
+        .collect()
+}
+
+/// Text of a cell, with line breaks and list items on lines of their own.
+fn cell_text(cell: ElementRef) -> String {
+    fn walk(element: ElementRef, out: &mut String) {
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => out.push_str(text),
+                Node::Element(e) if e.name() == "br" => out.push('\n'),
+                Node::Element(e) if matches!(e.name(), "style" | "script") => {},
+                Node::Element(e) => {
+                    let block = matches!(e.name(), "li" | "p" | "div");
+                    if block {
+                        out.push('\n');
+                    }
+                    if let Some(child) = ElementRef::wrap(child) {
+                        walk(child, out);
+                    }
+                    if block {
+                        out.push('\n');
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+    let mut out = String::new();
+    walk(cell, &mut out);
+    out.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn normalize(label: &str) -> String {
+    label.trim().to_lowercase()
+}
+
+fn absolute_url(src: &str, base_url: &str) -> String {
+    if src.starts_with("//") {
+        format!("https:{}", src)
+    } else if src.starts_with('/') {
+        format!("{}{}", base_url.trim_end_matches('/'), src)
+    } else {
+        src.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_starship_and_trait_infoboxes() {
+        let ship = r#"<div class="mw-parser-output"><table class="infobox ship-infobox">
+            <tr><th colspan="2">Defiant Class</th></tr>
+            <tr><td colspan="2"><img src="/images/thumb/defiant.png/250px-defiant.png"></td></tr>
+            <tr><th>Faction:</th><td>Federation</td></tr>
+            <tr><th>Tier</th><td>3</td></tr>
+            <tr><th>Hull Strength</th><td>27,500</td></tr>
+            <tr><th>Shield Modifier</th><td>0.9</td></tr>
+            <tr><th>Bridge Officers</th><td>Lt. Commander Tactical<br>Ensign Engineering</td></tr>
+            <tr><th>Fore Weapons</th><td>3</td></tr><tr><th>Aft Weapons</th><td>1</td></tr>
+            <tr><th>Engineering Consoles</th><td>2</td></tr><tr><th>Tactical Consoles</th><td>3</td></tr>
+            </table><p>The Defiant Class is an escort.</p></div>"#;
+        let infobox = Infobox::extract(ship, "https://stowiki.net").unwrap();
+        assert_eq!(infobox.kind, InfoboxKind::Starship);
+        assert_eq!(infobox.image.as_deref(), Some("https://stowiki.net/images/thumb/defiant.png/250px-defiant.png"));
+        assert_eq!(infobox.fields(), vec![
+            ("Tier".to_string(), "3".to_string()),
+            ("Faction".to_string(), "Federation".to_string()),
+            ("Hull".to_string(), "27,500".to_string()),
+            ("Shields".to_string(), "0.9".to_string()),
+            ("Bridge Officers".to_string(), "Lt. Commander Tactical\nEnsign Engineering".to_string()),
+            ("Weapons".to_string(), "Fore Weapons: 3\nAft Weapons: 1".to_string()),
+            ("Consoles".to_string(), "Engineering Consoles: 2\nTactical Consoles: 3".to_string()),
+        ]);
+
+        let space_trait = r#"<aside class="portable-infobox">
+            <div class="pi-item pi-data"><h3 class="pi-data-label">Type</h3><div class="pi-data-value">Space Trait</div></div>
+            <div class="pi-item pi-data"><h3 class="pi-data-label">Effect</h3><div class="pi-data-value">+10% Critical Chance</div></div>
+            </aside>"#;
+        let infobox = Infobox::extract(space_trait, "https://stowiki.net").unwrap();
+        assert_eq!(infobox.kind, InfoboxKind::Trait);
+        assert_eq!(infobox.fields()[1], ("Effect".to_string(), "+10% Critical Chance".to_string()));
+        assert!(Infobox::extract("<p>No infobox here</p>", "https://stowiki.net").is_none());
+    }
+}
@@ -0,0 +1,24 @@
+use chrono::Local;
+
+// Helper for logging errors with consistent format
+pub fn log_error(context: &str, error: impl std::fmt::Display) {
+    eprintln!("CEF:0|stobot|{}|{}|ERROR|Error|msg={} | Context: {}. time={}", 
+        env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), error, context, Local::now().to_rfc3339());
+}
+
+// Helper for logging recoverable failures with consistent format
+pub fn log_warn(message: &str, error: impl std::fmt::Display, context: &str) {
+    eprintln!("CEF:0|stobot|{}|{}|WARN|{}|msg={} | Context: {}. time={}",
+        env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), message, error, context, Local::now().to_rfc3339());
+}
+
+// Helper for logging info with consistent format
+pub fn log_info(message: &str, details: Option<&str>) {
+    if let Some(details) = details {
+        println!("CEF:0|stobot|{}|{}|INFO|{}|msg={} time={}", 
+            env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), message, details, Local::now().to_rfc3339());
+    } else {
+        println!("CEF:0|stobot|{}|{}|INFO|{}|time={}", 
+            env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), message, Local::now().to_rfc3339());
+    }
+}
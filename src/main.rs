@@ -1,40 +1,128 @@
 mod news;
 mod handler;
 mod arc_api;
+mod store;
+mod poller;
+mod logging;
+mod platform;
+mod category;
+mod filter;
+mod ping;
+mod markdown;
+mod pager;
+mod search;
+mod backfill;
+mod revision;
+mod wiki;
+mod infobox;
+mod wiki_cache;
+#[cfg(test)]
+mod test_support;
 
-use std::collections::BTreeSet;
 use std::env;
-use clap::Parser;
+use std::sync::Arc;
+use std::time::Duration;
+use clap::{Parser, Subcommand};
 use serenity::prelude::*;
-use crate::handler::Handler;
+use crate::news::{set_source_timezone, DEFAULT_SOURCE_TIMEZONE};
+use crate::platform::parse_platforms;
+use crate::handler::{Handler, HandlerConfig};
+use crate::logging::log_warn;
+use crate::arc_api::{ArcClient, DEFAULT_BASE_URL};
+use crate::backfill::backfill;
+use crate::poller::{PollMetrics, Poller, PollerConfig, DEFAULT_MAX_CATCH_UP, DEFAULT_POLL_COUNT};
+use crate::store::{NewsArchive, SqliteStore, Store};
+use crate::wiki::{WikiClient, DEFAULT_WIKI_URL};
+use crate::wiki_cache::{CacheConfig, WikiCache};
 use chrono::Local; // Add this import for timestamps
 use tokio::signal;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Parser)]
 struct Args {
-    /// Path to saved channels
+    /// Path to the legacy saved channels file, imported into the database once on first start
     #[clap(short, long, default_value = "channels.txt")]
     channels_path: String,
 
+    /// Path to the SQLite database holding channel subscriptions
+    #[clap(long, default_value = "stobot.db")]
+    db_path: String,
+
     /// Time in seconds inbetween checking for news
     #[clap(long, default_value_t = 600)]
     poll_period: u64,
 
     /// Number of news to poll in each period
-    #[clap(long, default_value_t = 20)]
+    #[clap(long, default_value_t = DEFAULT_POLL_COUNT)]
     poll_count: u64,
 
-    /// Maximum time difference in seconds between now and timestamp of news item to be even considered for posting
-    #[clap(short, long, default_value_t = 600)]
-    fresh_seconds: u64,
+    /// Base URL of the ARC Games STO news API. Can also be set with the ARC_API_URL environment variable
+    #[clap(long, default_value = DEFAULT_BASE_URL)]
+    api_url: String,
+
+    /// Base URL of STOWiki. Can also be set with the STOWIKI_URL environment variable
+    #[clap(long, default_value = DEFAULT_WIKI_URL)]
+    wiki_url: String,
+
+    /// Seconds a STOWiki answer is reused before asking the wiki whether it changed
+    #[clap(long, default_value_t = 3600)]
+    wiki_cache_ttl: u64,
+
+    /// Most STOWiki answers kept in memory
+    #[clap(long, default_value_t = 500)]
+    wiki_cache_size: usize,
+
+    /// SQLite file STOWiki answers are also kept in, so they survive restarts, for up to 30 days. Kept in memory only if unset
+    #[clap(long)]
+    wiki_cache_path: Option<String>,
+
+    /// IANA timezone the ARC Games API reports news times in. Can also be set with the ARC_TIMEZONE environment variable
+    #[clap(long, default_value_t = DEFAULT_SOURCE_TIMEZONE.name().to_string())]
+    source_timezone: String,
+
+    /// Timeout in seconds for each request to the ARC Games API
+    #[clap(long, default_value_t = 30)]
+    api_timeout: u64,
+
+    /// Number of times a failed ARC Games API request is retried, with exponentially growing delays
+    #[clap(long, default_value_t = 3)]
+    api_retries: u32,
+
+    /// Most missed news items posted to a channel at once after the bot was down, older ones are skipped.
+    /// Only news beyond --poll-count is fetched to catch up if this is larger
+    #[clap(long, default_value_t = DEFAULT_MAX_CATCH_UP)]
+    max_catch_up: usize,
 
-    /// Amount of Discord messages to check for already posted news items during each poll. Discord has a limitation of 100.
+    /// Deprecated and ignored, channel watermarks decide what is new. Kept so existing command lines still start
+    #[clap(short, long, hide = true)]
+    fresh_seconds: Option<u64>,
+
+    /// Amount of Discord messages to scan for already posted news items in channels that have no delivery history yet.
+    /// Discord has a limitation of 100, 0 disables the scan.
     #[clap(short, long, default_value_t = 10)]
     msg_count: u8,
 
-    /// Space separated list of platforms to filter news from. E.g.: to have news from all 3: `pc ps xbox`
+    /// Space separated list of platforms newly registered channels get news from. E.g.: to have news from all 3: `pc ps xbox`.
+    /// Can also be set with the STOBOT_PLATFORMS environment variable
     #[clap(default_values_t = vec!["pc".to_string(), "xbox".to_string(), "ps".to_string()], num_args = 0..)]
-    platforms: Vec<String>
+    platforms: Vec<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Copy the news history into the local archive used by searches, then exit. Doesn't connect to Discord
+    Backfill {
+        /// Stop after this many pages of news
+        #[clap(long)]
+        max_pages: Option<usize>,
+
+        /// Keep going past pages that are already archived, refreshing the whole history
+        #[clap(long)]
+        full: bool,
+    },
 }
 
 #[tokio::main]
@@ -49,34 +137,120 @@ async fn main() {
         | GatewayIntents::GUILD_INTEGRATIONS;
     
     let mut args = Args::parse();
-    if let Ok(env_poll) = std::env::var("POLL_PERIOD") {
-        if let Ok(val) = env_poll.parse::<u64>() {
-            args.poll_period = val;
-        }
+    if let Ok(env_poll) = std::env::var("POLL_PERIOD")
+        && let Ok(val) = env_poll.parse::<u64>() {
+        args.poll_period = val;
+    }
+    if let Ok(env_platforms) = std::env::var("STOBOT_PLATFORMS") {
+        args.platforms = env_platforms.split([',', ' ']).map(|s| s.to_string()).collect();
+    }
+    if let Ok(env_api_url) = std::env::var("ARC_API_URL") {
+        args.api_url = env_api_url;
     }
-    println!("CEF:0|stobot|{}|{}|INFO|Saved channels path|msg={} time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), args.channels_path, Local::now().to_rfc3339());
+    if let Ok(env_wiki_url) = std::env::var("STOWIKI_URL") {
+        args.wiki_url = env_wiki_url;
+    }
+    if let Ok(env_timezone) = std::env::var("ARC_TIMEZONE") {
+        args.source_timezone = env_timezone;
+    }
+    println!("CEF:0|stobot|{}|{}|INFO|Database path|msg={} time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), args.db_path, Local::now().to_rfc3339());
+    println!("CEF:0|stobot|{}|{}|INFO|ARC API URL|msg={} time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), args.api_url, Local::now().to_rfc3339());
+    println!("CEF:0|stobot|{}|{}|INFO|STOWiki URL|msg={} time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), args.wiki_url, Local::now().to_rfc3339());
     println!("CEF:0|stobot|{}|{}|INFO|Polling period|msg={} time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), args.poll_period, Local::now().to_rfc3339());
     println!("CEF:0|stobot|{}|{}|INFO|Poll count|msg={} time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), args.poll_count, Local::now().to_rfc3339());
-    println!("CEF:0|stobot|{}|{}|INFO|Fresh seconds|msg={} time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), args.fresh_seconds, Local::now().to_rfc3339());
+    println!("CEF:0|stobot|{}|{}|INFO|Catch-up limit|msg={} time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), args.max_catch_up, Local::now().to_rfc3339());
+    if let Some(fresh_seconds) = args.fresh_seconds {
+        log_warn("Deprecated option ignored", format!("--fresh-seconds {}", fresh_seconds), "Channel watermarks decide which news is new, remove the option");
+    }
     println!("CEF:0|stobot|{}|{}|INFO|Messages to check|msg={} time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), args.msg_count, Local::now().to_rfc3339());
     
-    // Create default platforms set with all platforms enabled
-    let default_platforms = BTreeSet::from_iter(vec!["pc".to_string(), "xbox".to_string(), "ps".to_string()]);
+    let default_platforms = match parse_platforms(&args.platforms) {
+        Ok(platforms) => platforms,
+        Err(e) => {
+            eprintln!("CEF:0|stobot|{}|{}|ERROR|Invalid platforms|msg={} | Context: Parsing default platforms {:?}. time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), e, args.platforms, Local::now().to_rfc3339());
+            std::process::exit(2);
+        }
+    };
+    println!("CEF:0|stobot|{}|{}|INFO|Default platforms|msg={:?} time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), default_platforms, Local::now().to_rfc3339());
+
+    match args.source_timezone.trim().parse::<chrono_tz::Tz>() {
+        Ok(tz) => {
+            set_source_timezone(tz);
+            println!("CEF:0|stobot|{}|{}|INFO|ARC API timezone|msg={} time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), tz.name(), Local::now().to_rfc3339());
+        },
+        Err(e) => {
+            eprintln!("CEF:0|stobot|{}|{}|ERROR|Invalid timezone|msg={} | Context: Parsing ARC API timezone {:?}. time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), e, args.source_timezone, Local::now().to_rfc3339());
+            std::process::exit(2);
+        }
+    }
     
+    let store = SqliteStore::open(&args.db_path).expect("Couldn't open the subscription database");
+    match store.import_legacy_channels(&args.channels_path, &default_platforms) {
+        Ok(Some(count)) => println!("CEF:0|stobot|{}|{}|INFO|Imported legacy channels|msg={} channels from {} time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), count, args.channels_path, Local::now().to_rfc3339()),
+        Ok(None) => {},
+        Err(e) => eprintln!("CEF:0|stobot|{}|{}|ERROR|Legacy channel import failed|msg={} | Context: Importing {} into {}. time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), e, args.channels_path, args.db_path, Local::now().to_rfc3339()),
+    }
+
+    let arc = ArcClient::new(&args.api_url, Duration::from_secs(args.api_timeout), args.api_retries)
+        .expect("Couldn't create the ARC Games API client");
+
+    if let Some(Command::Backfill { max_pages, full }) = args.command.take() {
+        match backfill(&arc, &store, max_pages, full).await {
+            Ok(report) => println!("CEF:0|stobot|{}|{}|INFO|Backfill finished|msg=Pages:{} Items:{} Added:{} Archived:{} Complete:{} time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), report.pages, report.items, report.added, store.archived_count().unwrap_or_default(), report.complete, Local::now().to_rfc3339()),
+            Err(e) => {
+                eprintln!("CEF:0|stobot|{}|{}|ERROR|Backfill failed|msg={} | Context: Archiving news from {}. time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), e, args.api_url, Local::now().to_rfc3339());
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let cache_config = CacheConfig {
+        ttl: Duration::from_secs(args.wiki_cache_ttl),
+        capacity: args.wiki_cache_size,
+        path: args.wiki_cache_path.clone(),
+    };
+    println!("CEF:0|stobot|{}|{}|INFO|STOWiki cache|msg=TTL:{}s Size:{} Path:{} time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), args.wiki_cache_ttl, args.wiki_cache_size, args.wiki_cache_path.as_deref().unwrap_or("-"), Local::now().to_rfc3339());
+    let wiki_cache = WikiCache::open(&cache_config).expect("Couldn't open the STOWiki cache");
+    let wiki = WikiClient::new(&args.wiki_url, Duration::from_secs(args.api_timeout), wiki_cache)
+        .expect("Couldn't create the STOWiki client");
+
+    let store: Arc<dyn Store> = Arc::new(store);
+    let poll_metrics = Arc::new(PollMetrics::default());
     let handler = Handler::new(
-        args.poll_period,
-        args.poll_count,
-        args.channels_path,
-        args.fresh_seconds,
-        args.msg_count,
-        default_platforms,
+        HandlerConfig {
+            poll_period: args.poll_period,
+            default_platforms: default_platforms.clone(),
+        },
+        store.clone(),
+        arc.clone(),
+        wiki,
+        poll_metrics.clone(),
     );
     
     let token = env::var("DISCORD_TOKEN").expect("DISCORD_TOKEN environment variable is unset!");
     let mut client =
         Client::builder(&token, intents).event_handler(handler).await.expect("Err creating client");
 
+    // The poller only needs the REST API, so it is started exactly once here rather than on every gateway `ready`
+    let shutdown = CancellationToken::new();
+    let poller = Poller::new(
+        PollerConfig {
+            poll_period: args.poll_period,
+            poll_count: args.poll_count,
+            max_catch_up: args.max_catch_up,
+            msg_count: args.msg_count,
+            default_platforms,
+        },
+        store,
+        arc,
+        client.http.clone(),
+        poll_metrics,
+    );
+    let poller_handle = poller.spawn(shutdown.clone());
+
     // Spawn the client in a background task
+    let shard_manager = client.shard_manager.clone();
     let client_handle = tokio::spawn(async move {
         if let Err(why) = client.start().await {
             eprintln!("CEF:0|stobot|{}|{}|ERROR|Client error|msg=Failed to start client. Error: {} | Context: Starting Discord client with token. time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), why, Local::now().to_rfc3339());
@@ -87,6 +261,9 @@ async fn main() {
     signal::ctrl_c().await.expect("Failed to listen for shutdown signal");
     println!("CEF:0|stobot|{}|{}|INFO|Shutdown signal received|time={}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), Local::now().to_rfc3339());
 
-    // Optionally, wait for the client to finish
+    // Let the poller finish any in-flight sends before closing the gateway connection
+    shutdown.cancel();
+    let _ = poller_handle.await;
+    shard_manager.shutdown_all().await;
     let _ = client_handle.await;
 }
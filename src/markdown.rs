@@ -0,0 +1,287 @@
+// Conversion of article HTML to Discord-flavoured markdown, and splitting it to fit Discord's limits
+use scraper::{ElementRef, Html, Node};
+
+/// Maximum length of a message's content.
+pub const MESSAGE_LIMIT: usize = 2000;
+/// Maximum length of an embed description.
+pub const EMBED_DESCRIPTION_LIMIT: usize = 4096;
+/// Maximum length of an embed title.
+pub const EMBED_TITLE_LIMIT: usize = 256;
+/// Maximum length of an embed field value.
+pub const EMBED_FIELD_VALUE_LIMIT: usize = 1024;
+
+/// Site that relative links in articles point to.
+const ARTICLE_SITE: &str = "https://playstartrekonline.com";
+
+/// Convert an HTML fragment to markdown, keeping headings, lists, links and inline emphasis.
+pub fn html_to_markdown(html: &str) -> String {
+    let fragment = Html::parse_fragment(html);
+    let mut out = String::new();
+    render_children(fragment.root_element(), &mut out, 0);
+    tidy(&out)
+}
+
+fn render_children(element: ElementRef, out: &mut String, list_depth: usize) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => push_text(out, text),
+            Node::Element(_) => {
+                if let Some(child) = ElementRef::wrap(child) {
+                    render_element(child, out, list_depth);
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+fn render_element(element: ElementRef, out: &mut String, list_depth: usize) {
+    let name = element.value().name();
+    match name {
+        "script" | "style" | "head" | "iframe" => {},
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            // Discord only knows three heading levels
+            let level = name[1..].parse::<usize>().unwrap_or(3).min(3);
+            block_break(out);
+            out.push_str(&"#".repeat(level));
+            out.push(' ');
+            out.push_str(inline_text(element).trim());
+            out.push_str("\n\n");
+        },
+        "p" | "div" | "section" | "article" => {
+            block_break(out);
+            render_children(element, out, list_depth);
+            block_break(out);
+        },
+        "br" => out.push('\n'),
+        "hr" => {
+            block_break(out);
+            out.push_str("───\n\n");
+        },
+        "strong" | "b" => wrap_inline(element, out, "**", list_depth),
+        "em" | "i" => wrap_inline(element, out, "*", list_depth),
+        "u" => wrap_inline(element, out, "__", list_depth),
+        "s" | "del" | "strike" => wrap_inline(element, out, "~~", list_depth),
+        "code" => {
+            out.push('`');
+            out.push_str(&element.text().collect::<String>().replace('`', "'"));
+            out.push('`');
+        },
+        "a" => {
+            let text = inline_text(element);
+            let text = text.trim();
+            match element.value().attr("href").map(absolute_url) {
+                Some(href) if !text.is_empty() && text != href => out.push_str(&format!("[{}]({})", text, href)),
+                Some(href) => out.push_str(&href),
+                None => out.push_str(text),
+            }
+        },
+        "ul" | "ol" => {
+            if list_depth == 0 {
+                block_break(out);
+            } else if !out.ends_with('\n') {
+                out.push('\n');
+            }
+            let ordered = name == "ol";
+            let items = element.children().filter_map(ElementRef::wrap).filter(|c| c.value().name() == "li");
+            for (index, item) in items.enumerate() {
+                out.push_str(&"  ".repeat(list_depth));
+                if ordered {
+                    out.push_str(&format!("{}. ", index + 1));
+                } else {
+                    out.push_str("- ");
+                }
+                let mut body = String::new();
+                render_children(item, &mut body, list_depth + 1);
+                out.push_str(body.trim_matches(|c: char| c == ' ' || c == '\n'));
+                out.push('\n');
+            }
+            if list_depth == 0 {
+                out.push('\n');
+            }
+        },
+        "blockquote" => {
+            block_break(out);
+            let mut body = String::new();
+            render_children(element, &mut body, list_depth);
+            for line in tidy(&body).lines() {
+                out.push_str("> ");
+                out.push_str(line);
+                out.push('\n');
+            }
+            out.push('\n');
+        },
+        "tr" => {
+            let cells: Vec<String> = element.children()
+                .filter_map(ElementRef::wrap)
+                .map(|cell| inline_text(cell).trim().to_string())
+                .collect();
+            out.push_str(&cells.join(" | "));
+            out.push('\n');
+        },
+        "img" => {
+            if let Some(alt) = element.value().attr("alt").filter(|alt| !alt.trim().is_empty()) {
+                out.push_str(&escape(alt.trim()));
+            }
+        },
+        _ => render_children(element, out, list_depth),
+    }
+}
+
+fn wrap_inline(element: ElementRef, out: &mut String, marker: &str, list_depth: usize) {
+    let mut inner = String::new();
+    render_children(element, &mut inner, list_depth);
+    let trimmed = inner.trim();
+    if trimmed.is_empty() {
+        return;
+    }
+    // Markers must hug the text, so move surrounding whitespace outside of them
+    if inner.starts_with(char::is_whitespace) {
+        push_space(out);
+    }
+    out.push_str(marker);
+    out.push_str(trimmed);
+    out.push_str(marker);
+    if inner.ends_with(char::is_whitespace) {
+        out.push(' ');
+    }
+}
+
+fn inline_text(element: ElementRef) -> String {
+    let mut text = String::new();
+    render_children(element, &mut text, 0);
+    text.replace('\n', " ")
+}
+
+fn push_text(out: &mut String, text: &str) {
+    let mut words = text.split_whitespace().peekable();
+    if words.peek().is_none() {
+        if !text.is_empty() {
+            push_space(out);
+        }
+        return;
+    }
+    if text.starts_with(char::is_whitespace) {
+        push_space(out);
+    }
+    let escaped = words.map(escape).collect::<Vec<_>>().join(" ");
+    out.push_str(&escaped);
+    if text.ends_with(char::is_whitespace) {
+        out.push(' ');
+    }
+}
+
+fn push_space(out: &mut String) {
+    if !out.is_empty() && !out.ends_with(char::is_whitespace) {
+        out.push(' ');
+    }
+}
+
+fn block_break(out: &mut String) {
+    let trimmed = out.trim_end_matches(' ').len();
+    out.truncate(trimmed);
+    if !out.is_empty() && !out.ends_with("\n\n") {
+        out.push_str(if out.ends_with('\n') { "\n" } else { "\n\n" });
+    }
+}
+
+/// `text` cut to at most `limit` characters, ending in an ellipsis if anything was cut.
+pub fn truncate(text: &str, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(limit - 1).collect();
+    cut.push('…');
+    cut
+}
+
+/// Escape the characters Discord would read as markdown.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '~' | '`' | '|') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn absolute_url(href: &str) -> String {
+    if href.starts_with("//") {
+        format!("https:{}", href)
+    } else if href.starts_with('/') {
+        format!("{}{}", ARTICLE_SITE, href)
+    } else {
+        href.to_string()
+    }
+}
+
+/// Trim trailing spaces from lines and collapse runs of blank lines.
+fn tidy(text: &str) -> String {
+    let mut result = String::new();
+    let mut blank_lines = 0;
+    for line in text.lines().map(str::trim_end) {
+        if line.trim().is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+        if !result.is_empty() {
+            result.push_str(if blank_lines > 0 { "\n\n" } else { "\n" });
+        }
+        blank_lines = 0;
+        result.push_str(line);
+    }
+    result
+}
+
+/// Split markdown into chunks of at most `limit` characters.
+///
+/// Splits between paragraphs where possible, then between lines, then between words, and only cuts
+/// through a word when it alone is longer than `limit`.
+pub fn split_markdown(text: &str, limit: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut rest = text.trim();
+    while rest.chars().count() > limit {
+        let cut = rest.char_indices().nth(limit).map_or(rest.len(), |(i, _)| i);
+        let window = &rest[..cut];
+        let split_at = window.rfind("\n\n")
+            .or_else(|| window.rfind('\n'))
+            .or_else(|| window.rfind(' '))
+            .filter(|&i| i > 0)
+            .unwrap_or(cut);
+        chunks.push(rest[..split_at].trim_end().to_string());
+        rest = rest[split_at..].trim_start();
+    }
+    if !rest.is_empty() {
+        chunks.push(rest.to_string());
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_article_html() {
+        let html = r#"<h2>Bug Fixes</h2><p>Fixed <b>two</b> issues in <a href="/en/news/article/1">the event</a>:</p>
+            <ul><li>Tribbles no longer multiply_forever</li><li>Consoles:<ol><li>Xbox</li><li>PS</li></ol></li></ul>
+            <p>Line one<br>Line two</p><script>alert(1)</script>"#;
+        assert_eq!(html_to_markdown(html), "## Bug Fixes\n\n\
+            Fixed **two** issues in [the event](https://playstartrekonline.com/en/news/article/1):\n\n\
+            - Tribbles no longer multiply\\_forever\n\
+            - Consoles:\n  1. Xbox\n  2. PS\n\n\
+            Line one\nLine two");
+    }
+
+    #[test]
+    fn splits_at_paragraphs_then_words() {
+        let text = format!("{}\n\n{}", "a".repeat(15), "word ".repeat(10));
+        let chunks = split_markdown(&text, 20);
+        assert_eq!(chunks[0], "a".repeat(15));
+        assert!(chunks.iter().all(|c| c.chars().count() <= 20));
+        assert_eq!(chunks[1..].join(" "), "word ".repeat(10).trim());
+        assert_eq!(split_markdown(&"x".repeat(45), 20).len(), 3);
+    }
+}
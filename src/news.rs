@@ -1,12 +1,21 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashMap};
 use std::fmt::Debug;
 use std::slice::Iter;
-use serde::Deserialize;
+use std::sync::OnceLock;
+use serde::{Deserialize, Deserializer};
 use serde_aux::prelude::*;
-use chrono::{NaiveDateTime, TimeZone, Utc};
-use chrono::LocalResult::*;
-use chrono_tz::America::Los_Angeles;
+use chrono::{DateTime, Duration, LocalResult, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
 use chrono::Local; // Add this import for timestamps
+use serenity::builder::{CreateEmbed, CreateEmbedFooter};
+
+use crate::category::Category;
+use crate::filter::FilterSet;
+use crate::markdown::{html_to_markdown, split_markdown, EMBED_DESCRIPTION_LIMIT};
+use crate::platform::{platform_row, Platform, PlatformIcon};
+
+/// How far in the future an item may be dated and still count as current, to allow for clock skew.
+pub const FUTURE_TOLERANCE_SECONDS: i64 = 5 * 60;
 
 #[derive(Deserialize, Clone)]
 pub struct News {
@@ -14,9 +23,13 @@ pub struct News {
 }
 
 impl News {
+    pub fn from_items(news: Vec<NewsItem>) -> News {
+        News { news }
+    }
+
     pub fn filter_news_by_platform(&mut self, platforms: &BTreeSet<String>) -> bool{
         self.news.retain(|item| !platforms.is_disjoint(&item.platforms));
-        if self.news.len() >= 1 {
+        if !self.news.is_empty() {
             true
         }
         else {
@@ -25,9 +38,42 @@ impl News {
         }
     }
 
-    pub fn iter(&self) -> Iter<NewsItem> {
+    /// A copy holding only the items published for at least one of `platforms`.
+    pub fn for_platforms(&self, platforms: &BTreeSet<String>) -> News {
+        News {
+            news: self.news.iter().filter(|item| !platforms.is_disjoint(&item.platforms)).cloned().collect()
+        }
+    }
+
+    /// A copy holding only the items in at least one of `categories`, all of them if it is empty.
+    pub fn for_categories(&self, categories: &BTreeSet<String>) -> News {
+        News {
+            news: self.news.iter().filter(|item| item.in_categories(categories)).cloned().collect()
+        }
+    }
+
+    /// A copy holding only the items whose title and summary pass `filters`.
+    pub fn matching(&self, filters: &FilterSet) -> News {
+        News {
+            news: self.news.iter().filter(|item| filters.matches(&item.title, &item.summary)).cloned().collect()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.news.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.news.is_empty()
+    }
+
+    pub fn iter(&self) -> Iter<'_, NewsItem> {
         self.news.iter()
     }
+
+    pub fn as_slice(&self) -> &[NewsItem] {
+        &self.news
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -37,48 +83,128 @@ pub struct NewsItem {
     title: String,
     summary: String,
     platforms: BTreeSet<String>,
-    updated: String,
-    images: std::collections::HashMap<String, std::collections::HashMap<String, String>>
+    updated: Timestamp,
+    images: HashMap<String, HashMap<String, String>>,
+    #[serde(default, deserialize_with = "deserialize_tags")]
+    tags: Vec<String>,
+}
+
+/// Format of the `updated` timestamps of the ARC API, a wall clock time in [`source_timezone`].
+const UPDATED_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Timezone the ARC API reports times in, unless configured otherwise.
+pub const DEFAULT_SOURCE_TIMEZONE: Tz = chrono_tz::America::Los_Angeles;
+
+static SOURCE_TIMEZONE: OnceLock<Tz> = OnceLock::new();
+
+/// Set the timezone of the API's timestamps. Only the first call has an effect, it must happen before any news is parsed.
+pub fn set_source_timezone(tz: Tz) {
+    let _ = SOURCE_TIMEZONE.set(tz);
+}
+
+pub fn source_timezone() -> Tz {
+    SOURCE_TIMEZONE.get().copied().unwrap_or(DEFAULT_SOURCE_TIMEZONE)
+}
+
+/// The instant a wall clock time in `tz` stands for.
+///
+/// A time in the hour repeated when clocks fall back is taken as its later occurrence, so an item can only seem newer
+/// than it is, never older than what a channel has already seen. A time skipped when clocks spring forward is read with
+/// the offset from before the change, as a clock that wasn't moved yet would show it.
+pub fn local_to_utc(naive: NaiveDateTime, tz: Tz) -> DateTime<Utc> {
+    match tz.from_local_datetime(&naive) {
+        LocalResult::Single(local) => local.with_timezone(&Utc),
+        LocalResult::Ambiguous(_, later) => later.with_timezone(&Utc),
+        LocalResult::None => {
+            // No gap is longer than a day, so a day earlier is safely before it
+            let before = tz.offset_from_utc_datetime(&(naive - Duration::days(1))).fix();
+            Utc.from_utc_datetime(&(naive - Duration::seconds(before.local_minus_utc() as i64)))
+        },
+    }
+}
+
+/// An API timestamp, as sent and as the instant it stands for, parsed once when the item is read.
+#[derive(Debug, Clone)]
+struct Timestamp {
+    raw: String,
+    utc: Option<DateTime<Utc>>,
+}
+
+impl Timestamp {
+    fn parse(raw: String) -> Timestamp {
+        let utc = NaiveDateTime::parse_from_str(raw.trim(), UPDATED_FORMAT).ok()
+            .map(|naive| local_to_utc(naive, source_timezone()));
+        Timestamp { raw, utc }
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Timestamp::parse)
+    }
+}
+
+/// Tags come either as plain strings or as objects carrying a `name`/`slug`, keep whichever is there.
+fn deserialize_tags<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let values: Option<Vec<serde_json::Value>> = Option::deserialize(deserializer)?;
+    Ok(values.unwrap_or_default().into_iter()
+        .filter_map(|value| match value {
+            serde_json::Value::String(tag) => Some(tag),
+            serde_json::Value::Object(tag) => tag.get("slug").or_else(|| tag.get("name"))
+                .and_then(|t| t.as_str())
+                .map(str::to_string),
+            _ => None,
+        })
+        .collect())
 }
 
 impl NewsItem {
+    /// Rebuild an item from the fields kept in the news archive.
+    pub fn from_archive(id: u64, title: String, summary: String, platforms: BTreeSet<String>, updated: String, thumbnail: Option<String>, tags: Vec<String>) -> NewsItem {
+        let images = thumbnail
+            .map(|url| HashMap::from([("img_microsite_thumbnail".to_string(), HashMap::from([("url".to_string(), url)]))]))
+            .unwrap_or_default();
+        NewsItem { id, title, summary, platforms, updated: Timestamp::parse(updated), images, tags }
+    }
+
     pub fn get_id(&self) -> u64 {
         self.id
     }
 
-    pub fn is_fresh(&self, diff_threshold: u64) -> bool {
-        if let Ok(naive) = NaiveDateTime::parse_from_str(&self.updated, "%Y-%m-%d %H:%M:%S") {
-            if let Single(pacific) = Los_Angeles.from_local_datetime(&naive) {
-                let diff = Utc::now().signed_duration_since(pacific);
-                diff.num_seconds().abs() as u64 <= diff_threshold
-            }
-            else {
-                false
-            }
-        }
-        else {
-            false
-        }
+    /// When the item was last updated, `None` if the API sent something unparseable.
+    pub fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.updated.utc
     }
 
+    /// Whether the item was updated within the last `weeks` weeks, and isn't dated later than clock skew explains.
     pub fn is_within_weeks(&self, weeks: u32) -> bool {
-        if let Ok(naive) = NaiveDateTime::parse_from_str(&self.updated, "%Y-%m-%d %H:%M:%S") {
-            if let Single(pacific) = Los_Angeles.from_local_datetime(&naive) {
-                let diff = Utc::now().signed_duration_since(pacific);
-                // Convert weeks to seconds for comparison (weeks * 7 days * 24 hours * 60 minutes * 60 seconds)
-                diff.num_seconds().abs() as u64 <= (weeks as u64 * 7 * 24 * 60 * 60)
-            } else {
-                false
-            }
-        } else {
-            false
-        }
+        self.updated_at().is_some_and(|updated| {
+            let age = Utc::now().signed_duration_since(updated);
+            age >= -Duration::seconds(FUTURE_TOLERANCE_SECONDS) && age <= Duration::weeks(weeks as i64)
+        })
     }
 
     pub fn get_title(&self) -> &str {
         &self.title
     }
 
+    pub fn get_summary(&self) -> &str {
+        &self.summary
+    }
+
+    pub fn get_platforms(&self) -> &BTreeSet<String> {
+        &self.platforms
+    }
+
+    /// The raw update timestamp as sent by the API, in the source timezone configured with [`set_source_timezone`].
+    pub fn get_updated(&self) -> &str {
+        &self.updated.raw
+    }
+
+    pub fn get_tags(&self) -> &[String] {
+        &self.tags
+    }
+
     pub fn get_thumbnail_url(&self) -> Option<&str> {
         self.images
             .get("img_microsite_thumbnail")
@@ -86,30 +212,162 @@ impl NewsItem {
             .map(|s| s.as_str())
     }
 
-    pub fn get_tag(&self) -> &str {
-        // Extract tag from URL or return an empty string
-        // For example, if URL contains "/patch-notes/", return "patch-notes"
-        if self.title.contains("Patch Notes") {
-            "patch-notes"
-        } else {
-            "star-trek-online"
+    /// The categories of this item according to its API tags.
+    pub fn categories(&self) -> BTreeSet<Category> {
+        Category::from_tags(&self.tags)
+    }
+
+    /// Whether the item belongs to any of the category names in `categories`; an empty set matches everything.
+    pub fn in_categories(&self, categories: &BTreeSet<String>) -> bool {
+        categories.is_empty() || self.categories().iter().any(|c| categories.contains(c.name()))
+    }
+
+    pub fn get_url(&self) -> String {
+        article_url(self.id)
+    }
+
+    /// Build the embed for this item, and return the platform icon it references, which must be attached to the message.
+    ///
+    /// All platforms the item shares with `selected_platforms` are listed in the footer; the icon is only used
+    /// when there is exactly one of them, since an embed can't show more than one.
+    pub fn to_embed(&self, selected_platforms: &BTreeSet<String>) -> (CreateEmbed, Option<&'static PlatformIcon>) {
+        let platforms = self.matching_platforms(selected_platforms);
+        let icon = match platforms.as_slice() {
+            [platform] => Some(platform.icon()),
+            _ => None,
+        };
+        (self.build_embed(&platforms, icon), icon)
+    }
+
+    /// Like [`NewsItem::to_embed`] without the icon, for messages that are edited and so can't rely on attachments.
+    pub fn to_plain_embed(&self, selected_platforms: &BTreeSet<String>) -> CreateEmbed {
+        self.build_embed(&self.matching_platforms(selected_platforms), None)
+    }
+
+    fn build_embed(&self, platforms: &[Platform], icon: Option<&PlatformIcon>) -> CreateEmbed {
+        let mut embed = CreateEmbed::default()
+            .title(self.get_title())
+            .url(self.get_url())
+            .description(self.summary.clone());
+        if let Some(img_url) = self.get_thumbnail_url() {
+            embed = embed.thumbnail(img_url);
+        }
+        if !platforms.is_empty() {
+            let mut footer = CreateEmbedFooter::new(platform_row(platforms));
+            if let Some(icon) = icon {
+                footer = footer.icon_url(icon.attachment_url());
+            }
+            embed = embed.footer(footer);
         }
+        embed
+    }
+
+    /// The platforms of this item that are also in `selected_platforms`, ignoring any the bot doesn't know.
+    pub fn matching_platforms(&self, selected_platforms: &BTreeSet<String>) -> Vec<Platform> {
+        let mut platforms: Vec<Platform> = self.platforms.iter()
+            .filter(|p| selected_platforms.contains(&p.to_lowercase()))
+            .filter_map(|p| Platform::from_alias(p))
+            .collect();
+        platforms.sort();
+        platforms.dedup();
+        platforms
+    }
+}
+
+/// Link to the page of a news article on playstartrekonline.com.
+pub fn article_url(id: u64) -> String {
+    format!("https://playstartrekonline.com/en/news/article/{}", id)
+}
+
+/// The full content of a news article, as returned by the single article endpoint.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Article {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub id: u64,
+    pub title: String,
+    /// HTML body of the article
+    #[serde(default)]
+    pub content: String,
+}
+
+impl Article {
+    /// The body as markdown, split into pages that each fit an embed description.
+    pub fn pages(&self) -> Vec<String> {
+        split_markdown(&html_to_markdown(&self.content), EMBED_DESCRIPTION_LIMIT)
     }
 
-    pub fn format_with_platforms(&self, selected_platforms: &BTreeSet<String>) -> (String, Vec<String>) {
-        let matching: Vec<&String> = self.platforms.iter().filter(|p| selected_platforms.contains(&p.to_lowercase())).collect();
-        let icon_files: Vec<String> = matching.iter().map(|p| match p.to_lowercase().as_str() {
-            "pc" => "static/pc.png".to_string(),
-            "ps" | "playstation" => "static/ps.png".to_string(),
-            "xbox" => "static/xbox.png".to_string(),
-            _ => "static/unknown.png".to_string(),
-        }).collect();
-        (self.summary.clone(), icon_files)
+    /// Embed showing one of the [`Article::pages`].
+    pub fn page_embed(&self, text: &str) -> CreateEmbed {
+        CreateEmbed::default()
+            .title(&self.title)
+            .url(article_url(self.id))
+            .description(text)
     }
 }
 
+#[derive(Deserialize)]
+pub(crate) struct ArticleResponse {
+    pub details: Article,
+}
+
 impl PartialEq for NewsItem{
     fn eq(&self, other: &Self) -> bool {
         self.id == other.id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use super::*;
+
+    fn at(date: (i32, u32, u32), time: (u32, u32)) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(date.0, date.1, date.2).unwrap().and_hms_opt(time.0, time.1, 0).unwrap()
+    }
+
+    fn utc(date: (i32, u32, u32), time: (u32, u32)) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&at(date, time))
+    }
+
+    #[test]
+    fn converts_times_across_dst_transitions() {
+        let la = chrono_tz::America::Los_Angeles;
+        assert_eq!(local_to_utc(at((2024, 7, 1), (12, 0)), la), utc((2024, 7, 1), (19, 0)));
+        // Spring forward: 02:00-03:00 doesn't exist, it's read as standard time
+        assert_eq!(local_to_utc(at((2024, 3, 10), (2, 30)), la), utc((2024, 3, 10), (10, 30)));
+        assert_eq!(local_to_utc(at((2024, 3, 10), (3, 0)), la), utc((2024, 3, 10), (10, 0)));
+        // Fall back: 01:00-02:00 happens twice, the later one counts
+        assert_eq!(local_to_utc(at((2024, 11, 3), (1, 30)), la), utc((2024, 11, 3), (9, 30)));
+        assert_eq!(local_to_utc(at((2024, 11, 3), (0, 59)), la), utc((2024, 11, 3), (7, 59)));
+
+        let berlin = chrono_tz::Europe::Berlin;
+        assert_eq!(local_to_utc(at((2024, 3, 31), (2, 30)), berlin), utc((2024, 3, 31), (1, 30)));
+        assert_eq!(local_to_utc(at((2024, 10, 27), (2, 30)), berlin), utc((2024, 10, 27), (1, 30)));
+    }
+
+    #[test]
+    fn leaves_future_news_out_of_recent_news() {
+        let dated = |offset: Duration| {
+            let updated = (Utc::now() + offset).with_timezone(&source_timezone()).format("%Y-%m-%d %H:%M:%S").to_string();
+            NewsItem::from_archive(1, "Title".to_string(), String::new(), BTreeSet::new(), updated, None, Vec::new())
+        };
+        assert!(dated(Duration::days(-3)).is_within_weeks(1));
+        assert!(!dated(Duration::days(-10)).is_within_weeks(1));
+        // A clock running a little behind the API's is fine, an item dated days ahead isn't recent
+        assert!(dated(Duration::minutes(1)).is_within_weeks(1));
+        assert!(!dated(Duration::days(3)).is_within_weeks(1));
+    }
+
+    #[test]
+    fn parses_updated_once_when_read() {
+        let item = |updated: &str| -> NewsItem {
+            serde_json::from_value(serde_json::json!({
+                "id": "1", "title": "t", "summary": "s", "platforms": ["pc"], "updated": updated, "images": {}
+            })).unwrap()
+        };
+        // Items from the repeated hour used to be dropped as ambiguous
+        assert_eq!(item("2024-11-03 01:30:00").updated_at(), Some(utc((2024, 11, 3), (9, 30))));
+        assert_eq!(item("2024-11-03 01:30:00").get_updated(), "2024-11-03 01:30:00");
+        assert_eq!(item("yesterday").updated_at(), None);
+    }
+}
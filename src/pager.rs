@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serenity::all::{ActionRow, ActionRowComponent, ButtonStyle, InputTextStyle};
+use serenity::builder::{
+    CreateActionRow, CreateButton, CreateEmbed, CreateInputText, CreateInteractionResponse,
+    CreateInteractionResponseFollowup, CreateInteractionResponseMessage, CreateModal,
+};
+
+/// Prefix of the custom IDs of the pager's buttons and modal, followed by `:<session>:<action>`.
+const CUSTOM_ID_PREFIX: &str = "stobot_page";
+
+/// Discord stops accepting edits of an interaction reply after 15 minutes, keep sessions that long.
+const SESSION_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Discord rejects messages with more than 10 embeds.
+pub const MAX_EMBEDS_PER_PAGE: usize = 10;
+
+/// One reply being paged through, keyed by the ID of the command interaction that created it.
+struct Session {
+    header: String,
+    pages: Vec<Vec<CreateEmbed>>,
+    current: usize,
+    created: Instant,
+}
+
+/// A page ready to be sent, as an initial reply or a followup.
+pub struct PageView {
+    content: String,
+    embeds: Vec<CreateEmbed>,
+    components: Vec<CreateActionRow>,
+}
+
+impl PageView {
+    pub fn message(self) -> CreateInteractionResponseMessage {
+        CreateInteractionResponseMessage::new()
+            .content(self.content)
+            .embeds(self.embeds)
+            .components(self.components)
+    }
+
+    pub fn followup(self) -> CreateInteractionResponseFollowup {
+        CreateInteractionResponseFollowup::new()
+            .content(self.content)
+            .embeds(self.embeds)
+            .components(self.components)
+    }
+}
+
+/// Keeps the pages of replies too long for one message, and turns them when their buttons are clicked.
+#[derive(Default)]
+pub struct Pager {
+    sessions: Mutex<HashMap<u64, Session>>,
+}
+
+impl Pager {
+    /// Begin paging `pages` for the interaction `id`, starting at `page` (counted from zero).
+    ///
+    /// A single page is shown without buttons and isn't kept.
+    pub fn start(&self, id: u64, header: String, pages: Vec<Vec<CreateEmbed>>, page: usize) -> PageView {
+        debug_assert!(pages.iter().all(|p| p.len() <= MAX_EMBEDS_PER_PAGE));
+        let session = Session {
+            current: page.min(pages.len().saturating_sub(1)),
+            header,
+            pages,
+            created: Instant::now(),
+        };
+        let view = render(id, &session);
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, s| s.created.elapsed() < SESSION_TTL);
+        if session.pages.len() > 1 {
+            sessions.insert(id, session);
+        }
+        view
+    }
+
+    /// The response to a click on a pager button, or `None` if the component isn't one of ours.
+    pub fn handle_component(&self, custom_id: &str) -> Option<CreateInteractionResponse> {
+        let (id, action) = parse_custom_id(custom_id)?;
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(&id).filter(|s| s.created.elapsed() < SESSION_TTL) else {
+            return Some(expired());
+        };
+        match action {
+            "prev" => session.current = session.current.saturating_sub(1),
+            "next" => session.current = (session.current + 1).min(session.pages.len() - 1),
+            "jump" => return Some(CreateInteractionResponse::Modal(
+                CreateModal::new(format!("{}:{}:goto", CUSTOM_ID_PREFIX, id), "Jump to page")
+                    .components(vec![CreateActionRow::InputText(
+                        CreateInputText::new(InputTextStyle::Short, format!("Page (1-{})", session.pages.len()), "page")
+                            .min_length(1)
+                            .max_length(4)
+                    )])
+            )),
+            _ => return None,
+        }
+        Some(CreateInteractionResponse::UpdateMessage(render(id, session).message()))
+    }
+
+    /// The response to the submitted "Jump to page" modal, or `None` if the modal isn't one of ours.
+    pub fn handle_modal(&self, custom_id: &str, components: &[ActionRow]) -> Option<CreateInteractionResponse> {
+        let (id, "goto") = parse_custom_id(custom_id)? else {
+            return None;
+        };
+        let input = components.iter()
+            .flat_map(|row| row.components.iter())
+            .find_map(|component| match component {
+                ActionRowComponent::InputText(input) if input.custom_id == "page" => input.value.clone(),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(&id).filter(|s| s.created.elapsed() < SESSION_TTL) else {
+            return Some(expired());
+        };
+        match input.trim().parse::<usize>() {
+            Ok(page) if (1..=session.pages.len()).contains(&page) => {
+                session.current = page - 1;
+                Some(CreateInteractionResponse::UpdateMessage(render(id, session).message()))
+            },
+            _ => Some(CreateInteractionResponse::Message(CreateInteractionResponseMessage::new()
+                .content(format!("`{}` is not a page number between 1 and {}.", input.trim(), session.pages.len()))
+                .ephemeral(true))),
+        }
+    }
+}
+
+fn parse_custom_id(custom_id: &str) -> Option<(u64, &str)> {
+    let mut parts = custom_id.splitn(3, ':');
+    if parts.next()? != CUSTOM_ID_PREFIX {
+        return None;
+    }
+    let id = parts.next()?.parse().ok()?;
+    Some((id, parts.next()?))
+}
+
+fn expired() -> CreateInteractionResponse {
+    CreateInteractionResponse::Message(CreateInteractionResponseMessage::new()
+        .content("These results have expired, please run the command again.")
+        .ephemeral(true))
+}
+
+fn render(id: u64, session: &Session) -> PageView {
+    let count = session.pages.len();
+    if count <= 1 {
+        return PageView {
+            content: session.header.clone(),
+            embeds: session.pages.first().cloned().unwrap_or_default(),
+            components: Vec::new(),
+        };
+    }
+    let button = |action: &str, label: &str| CreateButton::new(format!("{}:{}:{}", CUSTOM_ID_PREFIX, id, action))
+        .label(label)
+        .style(ButtonStyle::Secondary);
+    let position = format!("Page {} of {}", session.current + 1, count);
+    PageView {
+        content: if session.header.is_empty() { position } else { format!("{}\n{}", session.header, position) },
+        embeds: session.pages[session.current].clone(),
+        components: vec![CreateActionRow::Buttons(vec![
+            button("prev", "◀ Previous").disabled(session.current == 0),
+            button("next", "Next ▶").disabled(session.current + 1 == count),
+            button("jump", "Jump to…"),
+        ])],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turns_pages_of_a_session() {
+        let pager = Pager::default();
+        let pages = (1..=3).map(|n| vec![CreateEmbed::new().title(format!("Item {}", n))]).collect();
+        let first = serde_json::to_value(pager.start(7, "Results".to_string(), pages, 0).message()).unwrap();
+        assert_eq!(first["content"], "Results\nPage 1 of 3");
+        assert_eq!(first["components"][0]["components"][0]["disabled"], true);
+
+        let next = serde_json::to_value(pager.handle_component("stobot_page:7:next").unwrap()).unwrap();
+        assert_eq!(next["type"], 7);
+        assert_eq!(next["data"]["embeds"][0]["title"], "Item 2");
+
+        let jump = serde_json::to_value(pager.handle_component("stobot_page:7:jump").unwrap()).unwrap();
+        assert_eq!(jump["data"]["custom_id"], "stobot_page:7:goto");
+
+        let expired = serde_json::to_value(pager.handle_component("stobot_page:8:next").unwrap()).unwrap();
+        assert_eq!(expired["data"]["flags"], 64);
+        assert!(pager.handle_component("something_else").is_none());
+    }
+}
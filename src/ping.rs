@@ -0,0 +1,115 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use serenity::builder::CreateAllowedMentions;
+use serenity::model::id::RoleId;
+
+use crate::category::describe_categories;
+use crate::news::NewsItem;
+
+/// Who gets notified when news is posted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PingTarget {
+    Here,
+    Role(u64),
+}
+
+impl PingTarget {
+    pub fn mention(&self) -> String {
+        match self {
+            PingTarget::Here => "@here".to_string(),
+            PingTarget::Role(id) => format!("<@&{}>", id),
+        }
+    }
+
+    /// The form stored in the database.
+    pub fn key(&self) -> String {
+        match self {
+            PingTarget::Here => "here".to_string(),
+            PingTarget::Role(id) => format!("role:{}", id),
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<PingTarget> {
+        match key {
+            "here" => Some(PingTarget::Here),
+            _ => key.strip_prefix("role:")?.parse().ok().map(PingTarget::Role),
+        }
+    }
+}
+
+/// A mention added to news posts of a channel, optionally only for some categories or platforms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PingRule {
+    pub target: PingTarget,
+    /// Category names, an empty set means every category
+    pub categories: BTreeSet<String>,
+    /// Platform API names, an empty set means every platform
+    pub platforms: BTreeSet<String>,
+}
+
+impl PingRule {
+    pub fn applies_to(&self, item: &NewsItem) -> bool {
+        item.in_categories(&self.categories)
+            && (self.platforms.is_empty() || !item.matching_platforms(&self.platforms).is_empty())
+    }
+}
+
+impl fmt::Display for PingRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} for categories: {}", self.target.mention(), describe_categories(&self.categories))?;
+        if self.platforms.is_empty() {
+            write!(f, ", platforms: All")
+        } else {
+            write!(f, ", platforms: {:?}", self.platforms)
+        }
+    }
+}
+
+/// The message content and allowed mentions for a post of `items`, or `None` if no rule applies.
+///
+/// Only the targets of matching rules may be pinged, whatever else ends up in the message.
+pub fn mentions_for<'a>(rules: &[PingRule], items: impl IntoIterator<Item = &'a NewsItem>) -> Option<(String, CreateAllowedMentions)> {
+    let items: Vec<&NewsItem> = items.into_iter().collect();
+    let targets: BTreeSet<PingTarget> = rules.iter()
+        .filter(|rule| items.iter().any(|item| rule.applies_to(item)))
+        .map(|rule| rule.target)
+        .collect();
+    if targets.is_empty() {
+        return None;
+    }
+    let content = targets.iter().map(|t| t.mention()).collect::<Vec<_>>().join(" ");
+    let roles: Vec<RoleId> = targets.iter()
+        .filter_map(|t| match t {
+            PingTarget::Role(id) => Some(RoleId::new(*id)),
+            PingTarget::Here => None,
+        })
+        .collect();
+    let allowed = CreateAllowedMentions::new()
+        .roles(roles)
+        .everyone(targets.contains(&PingTarget::Here));
+    Some((content, allowed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::news::News;
+    use crate::test_support::news_fixture;
+
+    #[test]
+    fn mentions_only_matching_targets() {
+        let news: News = serde_json::from_str(&news_fixture()).unwrap();
+        let rules = vec![
+            PingRule { target: PingTarget::Role(7), categories: BTreeSet::from(["patch-notes".to_string()]), platforms: BTreeSet::new() },
+            PingRule { target: PingTarget::Here, categories: BTreeSet::new(), platforms: BTreeSet::from(["pc".to_string()]) },
+        ];
+        let patch_notes = news.iter().filter(|item| item.get_id() == 11100002);
+        let (content, allowed) = mentions_for(&rules, patch_notes).unwrap();
+        assert_eq!(content, "<@&7>");
+        assert_eq!(serde_json::to_value(allowed).unwrap(), serde_json::json!({"parse": [], "users": [], "roles": ["7"]}));
+
+        let (content, _) = mentions_for(&rules, news.iter()).unwrap();
+        assert_eq!(content, "@here <@&7>");
+        assert!(mentions_for(&rules[..1], news.iter().filter(|item| item.get_id() == 11100001)).is_none());
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+use serenity::builder::CreateAttachment;
+
+/// A platform logo compiled into the binary, so posting never depends on the working directory.
+pub struct PlatformIcon {
+    pub filename: &'static str,
+    pub bytes: &'static [u8],
+    /// Shown next to the platform name where an image can't be used
+    pub emoji: &'static str,
+}
+
+impl PlatformIcon {
+    /// The URL an embed uses to reference this icon once it is attached to the message.
+    pub fn attachment_url(&self) -> String {
+        format!("attachment://{}", self.filename)
+    }
+
+    pub fn attachment(&self) -> CreateAttachment {
+        CreateAttachment::bytes(self.bytes, self.filename)
+    }
+}
+
+static PC_ICON: PlatformIcon = PlatformIcon {
+    filename: "windows.png",
+    bytes: include_bytes!("../static/windows.png"),
+    emoji: "🖥️",
+};
+
+static XBOX_ICON: PlatformIcon = PlatformIcon {
+    filename: "xbox.png",
+    bytes: include_bytes!("../static/xbox.png"),
+    emoji: "🟩",
+};
+
+static PS_ICON: PlatformIcon = PlatformIcon {
+    filename: "playstation.png",
+    bytes: include_bytes!("../static/playstation.png"),
+    emoji: "🟦",
+};
+
+/// A platform STO news can be published for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Platform {
+    Pc,
+    Xbox,
+    Ps,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 3] = [Platform::Pc, Platform::Xbox, Platform::Ps];
+
+    /// The name used by the ARC Games API, which is also what gets stored per channel.
+    pub fn api_name(&self) -> &'static str {
+        match self {
+            Platform::Pc => "pc",
+            Platform::Xbox => "xbox",
+            Platform::Ps => "ps",
+        }
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Platform::Pc => "PC",
+            Platform::Xbox => "Xbox",
+            Platform::Ps => "PlayStation",
+        }
+    }
+
+    pub fn icon(&self) -> &'static PlatformIcon {
+        match self {
+            Platform::Pc => &PC_ICON,
+            Platform::Xbox => &XBOX_ICON,
+            Platform::Ps => &PS_ICON,
+        }
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            Platform::Pc => &["pc", "windows", "win", "steam"],
+            Platform::Xbox => &["xbox", "xb1", "xbone", "xbox one", "xbox series", "xsx"],
+            Platform::Ps => &["ps", "playstation", "ps4", "ps5"],
+        }
+    }
+
+    /// Look up a platform by its API name or one of its aliases, ignoring case and surrounding whitespace.
+    pub fn from_alias(name: &str) -> Option<Platform> {
+        let name = name.trim().to_lowercase();
+        Platform::ALL.into_iter().find(|p| p.aliases().contains(&name.as_str()))
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.display_name())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Platform::from_alias(s).ok_or_else(|| format!(
+            "Unknown platform `{}`. Valid platforms are {}.",
+            s.trim(),
+            Platform::ALL.iter().map(|p| format!("`{}` ({})", p.api_name(), p.display_name())).collect::<Vec<_>>().join(", ")
+        ))
+    }
+}
+
+/// One line listing `platforms` with their emoji, e.g. `🖥️ PC · 🟦 PlayStation`.
+pub fn platform_row(platforms: &[Platform]) -> String {
+    platforms.iter()
+        .map(|p| format!("{} {}", p.icon().emoji, p.display_name()))
+        .collect::<Vec<_>>()
+        .join(" · ")
+}
+
+/// Normalise a list of platform names or aliases to their API names, rejecting unknown ones.
+pub fn parse_platforms<S: AsRef<str>>(names: &[S]) -> Result<BTreeSet<String>, String> {
+    let mut platforms = BTreeSet::new();
+    for name in names.iter().map(|n| n.as_ref().trim()).filter(|n| !n.is_empty()) {
+        platforms.insert(name.parse::<Platform>()?.api_name().to_string());
+    }
+    if platforms.is_empty() {
+        return Err("Platform list cannot be empty.".to_string());
+    }
+    Ok(platforms)
+}
+
+/// Parse a comma-separated list as typed into a slash command option, e.g. `PC, Playstation`.
+pub fn parse_platform_list(input: &str) -> Result<BTreeSet<String>, String> {
+    parse_platforms(&input.split(',').collect::<Vec<_>>())
+}
+
+/// Autocomplete suggestions for a comma-separated platform list, completing the last entry.
+///
+/// Returns `(label, value)` pairs where the value is the whole list including the completed entry.
+pub fn complete_platform_list(input: &str) -> Vec<(String, String)> {
+    let (done, current) = match input.rsplit_once(',') {
+        Some((done, current)) => (Some(done), current),
+        None => (None, input),
+    };
+    let chosen: BTreeSet<Platform> = done
+        .map(|d| d.split(',').filter_map(Platform::from_alias).collect())
+        .unwrap_or_default();
+    let current = current.trim().to_lowercase();
+
+    Platform::ALL.iter()
+        .filter(|p| !chosen.contains(p))
+        .filter(|p| current.is_empty() || p.aliases().iter().any(|a| a.starts_with(&current)) || p.display_name().to_lowercase().starts_with(&current))
+        .map(|p| {
+            let mut list: Vec<Platform> = chosen.iter().copied().collect();
+            list.push(*p);
+            let label = list.iter().map(|p| p.display_name()).collect::<Vec<_>>().join(", ");
+            let value = list.iter().map(|p| p.api_name()).collect::<Vec<_>>().join(",");
+            (label, value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_aliases_case_insensitively() {
+        assert_eq!(parse_platform_list("PC, Playstation").unwrap(), BTreeSet::from(["pc".to_string(), "ps".to_string()]));
+        assert_eq!(parse_platform_list("xb1,PS5,windows").unwrap().len(), 3);
+        assert!(parse_platform_list("pc,switch").unwrap_err().contains("`switch`"));
+        assert!(parse_platform_list(" , ").is_err());
+    }
+
+    #[test]
+    fn completes_last_entry() {
+        assert_eq!(complete_platform_list("pc, play"), vec![("PC, PlayStation".to_string(), "pc,ps".to_string())]);
+        assert_eq!(complete_platform_list("").len(), 3);
+        assert_eq!(complete_platform_list("pc,xbox,").len(), 1);
+    }
+}
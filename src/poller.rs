@@ -0,0 +1,765 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use serenity::builder::{CreateAllowedMentions, CreateEmbed, CreateForumPost, CreateMessage, CreateThread, EditMessage, GetMessages};
+use serenity::http::{Http, StatusCode};
+use serenity::model::channel::{Channel, ChannelType, Message};
+use serenity::model::id::{ChannelId, MessageId};
+use regex::Regex;
+use tokio_util::sync::CancellationToken;
+
+use chrono::{DateTime, Utc};
+
+use crate::arc_api::{ArcClient, PAGE_SIZE};
+use crate::filter::FilterSet;
+use crate::logging::{log_error, log_info};
+use crate::markdown::{html_to_markdown, split_markdown, MESSAGE_LIMIT};
+use crate::news::{News, NewsItem, FUTURE_TOLERANCE_SECONDS};
+use crate::pager::MAX_EMBEDS_PER_PAGE;
+use crate::ping::{mentions_for, PingRule};
+use crate::platform::PlatformIcon;
+use crate::revision::{Revision, UpdateMode};
+use crate::store::{Store, Subscription, Watermark};
+
+/// Key of the channel setting holding the [`PostMode`].
+pub const POST_MODE_SETTING: &str = "post_mode";
+
+/// Longest name Discord accepts for a thread or forum post.
+const THREAD_NAME_LIMIT: usize = 100;
+
+/// How news is posted to a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostMode {
+    /// All new items together, as one message of embeds
+    Embed,
+    /// A forum post, or a thread in text channels, per item with the full article inside
+    Thread,
+}
+
+impl PostMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PostMode::Embed => "embed",
+            PostMode::Thread => "thread",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<PostMode> {
+        match name.trim().to_lowercase().as_str() {
+            "embed" => Some(PostMode::Embed),
+            "thread" | "forum" => Some(PostMode::Thread),
+            _ => None,
+        }
+    }
+
+    pub fn of(subscription: &Subscription) -> PostMode {
+        subscription.settings.get(POST_MODE_SETTING)
+            .and_then(|mode| PostMode::from_name(mode))
+            .unwrap_or(PostMode::Embed)
+    }
+}
+
+/// Counters describing the work done by the news poller since startup.
+#[derive(Default)]
+pub struct PollMetrics {
+    cycles: AtomicU64,
+    upstream_requests: AtomicU64,
+    requests_saved: AtomicU64,
+    restarts: AtomicU64,
+}
+
+impl PollMetrics {
+    /// Record a poll cycle that served `channels` channels from `requests` upstream requests.
+    ///
+    /// Before the feed was shared, every channel cost one request of its own.
+    pub fn record_cycle(&self, channels: usize, requests: usize) {
+        self.cycles.fetch_add(1, Ordering::Relaxed);
+        self.upstream_requests.fetch_add(requests as u64, Ordering::Relaxed);
+        self.requests_saved.fetch_add(channels.saturating_sub(requests) as u64, Ordering::Relaxed);
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles.load(Ordering::Relaxed)
+    }
+
+    pub fn upstream_requests(&self) -> u64 {
+        self.upstream_requests.load(Ordering::Relaxed)
+    }
+
+    pub fn requests_saved(&self) -> u64 {
+        self.requests_saved.load(Ordering::Relaxed)
+    }
+
+    pub fn restarts(&self) -> u64 {
+        self.restarts.load(Ordering::Relaxed)
+    }
+}
+
+/// News fetched per cycle unless catching up.
+pub const DEFAULT_POLL_COUNT: u64 = 20;
+
+/// Missed news posted per channel after a downtime. Larger than [`DEFAULT_POLL_COUNT`], or nothing beyond
+/// the regular fetch would ever be caught up on.
+pub const DEFAULT_MAX_CATCH_UP: usize = 40;
+
+pub struct PollerConfig {
+    pub poll_period: u64,
+    pub poll_count: u64,
+    /// Most items posted to one channel per cycle, and fetched per cycle, when catching up on missed news
+    pub max_catch_up: usize,
+    pub msg_count: u8,
+    /// Platforms for channels without a stored set
+    pub default_platforms: BTreeSet<String>,
+}
+
+/// Periodically fetches the news feed and posts new items to subscribed channels.
+pub struct Poller {
+    config: PollerConfig,
+    store: Arc<dyn Store>,
+    arc: ArcClient,
+    http: Arc<Http>,
+    metrics: Arc<PollMetrics>,
+}
+
+impl Poller {
+    pub fn new(config: PollerConfig, store: Arc<dyn Store>, arc: ArcClient, http: Arc<Http>, metrics: Arc<PollMetrics>) -> Poller {
+        Poller { config, store, arc, http, metrics }
+    }
+
+    /// Run the poller in a background task that is restarted whenever it panics, until `shutdown` is cancelled.
+    ///
+    /// The returned handle completes once the current poll cycle, including any in-flight sends, has finished.
+    pub fn spawn(self, shutdown: CancellationToken) -> tokio::task::JoinHandle<()> {
+        let poller = Arc::new(self);
+        tokio::spawn(async move {
+            loop {
+                let task = tokio::spawn({
+                    let poller = poller.clone();
+                    let shutdown = shutdown.clone();
+                    async move { poller.run(shutdown).await }
+                });
+                match task.await {
+                    Ok(()) => break,
+                    Err(e) if e.is_panic() => {
+                        poller.metrics.restarts.fetch_add(1, Ordering::Relaxed);
+                        log_error("News poller panicked, restarting", e);
+                        tokio::select! {
+                            _ = shutdown.cancelled() => break,
+                            _ = tokio::time::sleep(Duration::from_secs(5)) => {},
+                        }
+                    },
+                    Err(e) => {
+                        log_error("News poller stopped", e);
+                        break;
+                    }
+                }
+            }
+            log_info("News poller stopped", None);
+        })
+    }
+
+    async fn run(&self, shutdown: CancellationToken) {
+        log_info("News poller started", Some(&format!("Period:{}s", self.config.poll_period)));
+        while !shutdown.is_cancelled() {
+            self.poll_cycle(&shutdown).await;
+            tokio::select! {
+                _ = shutdown.cancelled() => {},
+                _ = tokio::time::sleep(Duration::from_secs(self.config.poll_period)) => {},
+            }
+        }
+    }
+
+    /// Fetch the feed once and deliver new items to every subscribed channel.
+    ///
+    /// Stops between channels once `shutdown` is cancelled, but never in the middle of a send.
+    async fn poll_cycle(&self, shutdown: &CancellationToken) {
+        let subscriptions = match self.store.list_subscriptions() {
+            Ok(subscriptions) => subscriptions,
+            Err(e) => {
+                log_error("Listing subscribed channels", e);
+                return;
+            }
+        };
+        if subscriptions.is_empty() {
+            return;
+        }
+        let news = match self.fetch_news(&subscriptions).await {
+            Some(news) => news,
+            None => return,
+        };
+        self.apply_revisions(&subscriptions, &news, shutdown).await;
+        for subscription in subscriptions.iter() {
+            if shutdown.is_cancelled() {
+                break;
+            }
+            let platforms = if subscription.platforms.is_empty() { &self.config.default_platforms } else { &subscription.platforms };
+            let filters = match FilterSet::new(&subscription.filters) {
+                Ok(filters) => filters,
+                Err(e) => {
+                    // Rules are validated when added, posting unfiltered could spam a channel that excluded things
+                    log_error(&format!("Compiling filter rules of channel {}", subscription.channel_id), e);
+                    continue;
+                }
+            };
+            let channel_news = news.for_platforms(platforms).for_categories(&subscription.categories).matching(&filters);
+            let unposted = if channel_news.is_empty() {
+                Vec::new()
+            } else {
+                self.deliver_news(subscription, &channel_news, platforms, shutdown).await
+            };
+            self.advance_watermark(subscription.channel_id, &news, &unposted);
+        }
+        log_info("Poll cycle finished", Some(&format!(
+            "Channels:{} Items:{} UpstreamRequests:{} RequestsSaved:{}",
+            subscriptions.len(), news.len(), self.metrics.upstream_requests(), self.metrics.requests_saved())));
+    }
+
+    /// The latest `poll_count` items, or as many as `max_catch_up` if a channel missed more than that.
+    async fn fetch_news(&self, subscriptions: &[Subscription]) -> Option<News> {
+        let mut requests = 1;
+        let mut news = match self.arc.latest_news(None, self.config.poll_count as u32).await {
+            Ok(news) => news,
+            Err(e) => {
+                log_error("Fetching news from API", e);
+                self.metrics.record_cycle(subscriptions.len(), requests);
+                return None;
+            }
+        };
+        let oldest_watermark = subscriptions.iter()
+            .filter_map(|s| self.store.watermark(s.channel_id).ok().flatten())
+            .map(|mark| mark.updated_at)
+            .min();
+        let oldest_fetched = news.iter().filter_map(|item| item.updated_at()).min();
+        let missed_more = news.len() as u64 >= self.config.poll_count
+            && matches!((oldest_watermark, oldest_fetched), (Some(mark), Some(fetched)) if fetched > mark);
+        if missed_more && self.config.max_catch_up as u64 > self.config.poll_count {
+            log_info("Catching up on missed news", Some(&format!("Since:{} Limit:{}", oldest_watermark.unwrap().to_rfc3339(), self.config.max_catch_up)));
+            requests += self.config.max_catch_up.div_ceil(PAGE_SIZE as usize);
+            match self.arc.latest_news(None, self.config.max_catch_up as u32).await {
+                Ok(more) => news = more,
+                // The first page still holds the newest items, post those rather than nothing
+                Err(e) => log_error("Fetching missed news from API", e),
+            }
+        }
+        self.metrics.record_cycle(subscriptions.len(), requests);
+        Some(news)
+    }
+
+    /// Post the pending items of `news` to the channel, returning those that could not be posted.
+    async fn deliver_news<'a>(&self, subscription: &Subscription, news: &'a News, channel_platforms: &BTreeSet<String>, shutdown: &CancellationToken) -> Vec<&'a NewsItem> {
+        let channel = ChannelId::new(subscription.channel_id);
+        self.bootstrap_ledger(channel).await;
+        let pending = self.pending_items(subscription.channel_id, news);
+        if pending.is_empty() {
+            return pending;
+        }
+        match PostMode::of(subscription) {
+            PostMode::Embed => {
+                for batch in pending.chunks(MAX_EMBEDS_PER_PAGE) {
+                    if shutdown.is_cancelled() {
+                        break;
+                    }
+                    self.post_embeds(channel, batch, channel_platforms, &subscription.pings).await;
+                }
+            },
+            PostMode::Thread => {
+                for item in pending.iter() {
+                    if shutdown.is_cancelled() {
+                        break;
+                    }
+                    self.post_thread(channel, item, channel_platforms, &subscription.pings).await;
+                }
+            },
+        }
+        pending.into_iter().filter(|item| !self.is_delivered(subscription.channel_id, item.get_id())).collect()
+    }
+
+    async fn post_embeds(&self, channel: ChannelId, pending: &[&NewsItem], channel_platforms: &BTreeSet<String>, pings: &[PingRule]) {
+        let mut embeds = Vec::new();
+        let mut icons = Vec::new();
+        for item in pending.iter() {
+            log_info("Sending news", Some(&format!("ID:{} Channel:{} Platforms:{:?}", item.get_id(), channel.get(), channel_platforms)));
+            let (embed, icon) = item.to_embed(channel_platforms);
+            if let Some(icon) = icon
+                && !icons.iter().any(|i: &&PlatformIcon| i.filename == icon.filename) {
+                icons.push(icon);
+            }
+            embeds.push(embed);
+        }
+        let mut msg = CreateMessage::default().embeds(embeds);
+        if let Some((content, allowed_mentions)) = mentions_for(pings, pending.iter().copied()) {
+            msg = msg.content(content).allowed_mentions(allowed_mentions);
+        }
+        for icon in icons {
+            msg = msg.add_file(icon.attachment());
+        }
+        match channel.send_message(&self.http, msg).await {
+            Ok(message) => {
+                for item in pending {
+                    self.record_delivery(channel.get(), item, message.id.get());
+                }
+            },
+            Err(e) => log_error("Failed to send scheduled news message", e),
+        }
+    }
+
+    /// Post one item as a forum post, or as a thread on its embed in other channels, followed by the full article.
+    async fn post_thread(&self, channel: ChannelId, item: &NewsItem, channel_platforms: &BTreeSet<String>, pings: &[PingRule]) {
+        log_info("Sending news as thread", Some(&format!("ID:{} Channel:{} Platforms:{:?}", item.get_id(), channel.get(), channel_platforms)));
+        let (embed, icon) = item.to_embed(channel_platforms);
+        let mut starter = CreateMessage::default().embed(embed);
+        if let Some(icon) = icon {
+            starter = starter.add_file(icon.attachment());
+        }
+        if let Some((content, allowed_mentions)) = mentions_for(pings, [item]) {
+            starter = starter.content(content).allowed_mentions(allowed_mentions);
+        }
+        let name: String = item.get_title().chars().take(THREAD_NAME_LIMIT).collect();
+        let is_forum = matches!(channel.to_channel(&self.http).await, Ok(Channel::Guild(c)) if c.kind == ChannelType::Forum);
+
+        let thread = if is_forum {
+            match channel.create_forum_post(&self.http, CreateForumPost::new(name, starter)).await {
+                Ok(thread) => {
+                    // The starter message of a forum post shares the post's ID
+                    self.record_delivery(channel.get(), item, thread.id.get());
+                    thread.id
+                },
+                Err(e) => {
+                    log_error("Creating news forum post", e);
+                    return;
+                }
+            }
+        } else {
+            let message = match channel.send_message(&self.http, starter).await {
+                Ok(message) => message,
+                Err(e) => {
+                    log_error("Failed to send scheduled news message", e);
+                    return;
+                }
+            };
+            self.record_delivery(channel.get(), item, message.id.get());
+            match channel.create_thread_from_message(&self.http, message.id, CreateThread::new(name)).await {
+                Ok(thread) => thread.id,
+                Err(e) => {
+                    log_error("Creating news thread", e);
+                    return;
+                }
+            }
+        };
+
+        for chunk in self.article_body(item).await {
+            // Article text is not ours, never let it ping anyone
+            let msg = CreateMessage::default().content(chunk).allowed_mentions(CreateAllowedMentions::new());
+            if let Err(e) = thread.send_message(&self.http, msg).await {
+                log_error("Posting article body to thread", e);
+                break;
+            }
+        }
+    }
+
+    /// The full article as markdown, split into message sized chunks. Empty if it can't be fetched.
+    async fn article_body(&self, item: &NewsItem) -> Vec<String> {
+        match self.arc.fetch_article(item.get_id()).await {
+            Ok(article) => split_markdown(&html_to_markdown(&article.content), MESSAGE_LIMIT),
+            Err(e) => {
+                log_error(&format!("Fetching article {}", item.get_id()), e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Record that `item` was posted.
+    fn record_delivery(&self, channel_id: u64, item: &NewsItem, message_id: u64) {
+        if let Err(e) = self.store.record_delivery(channel_id, item.get_id(), message_id) {
+            log_error("Recording news delivery", e);
+        }
+    }
+
+    /// Move the channel's watermark up to the newest item of `evaluated`, whether it matched the channel or not,
+    /// so later cycles don't consider it again. It stays below `unposted`, so those are tried again next cycle.
+    fn advance_watermark(&self, channel_id: u64, evaluated: &News, unposted: &[&NewsItem]) {
+        let Some(current) = self.channel_watermark(channel_id) else {
+            return;
+        };
+        let latest = Utc::now() + chrono::Duration::seconds(FUTURE_TOLERANCE_SECONDS);
+        // Items held back for being dated in the future must stay above the mark
+        let seen: Vec<(DateTime<Utc>, u64)> = evaluated.iter()
+            .filter_map(|item| item.updated_at().map(|updated| (updated, item.get_id())))
+            .filter(|(updated, _)| *updated <= latest)
+            .collect();
+        let Some(mut updated) = seen.iter().map(|(updated, _)| *updated).max() else {
+            return;
+        };
+        let mut news_id = seen.iter().map(|(_, id)| *id).max();
+        if !unposted.is_empty() {
+            // Whatever failed was above the current mark by date or by ID, keep it there
+            updated = unposted.iter().filter_map(|item| item.updated_at()).fold(updated, DateTime::min);
+            news_id = current.news_id;
+        }
+        // Never move back
+        let updated = updated.min(Utc::now()).max(current.updated_at);
+        let news_id = news_id.max(current.news_id);
+        if updated == current.updated_at && news_id == current.news_id {
+            return;
+        }
+        if let Err(e) = self.store.set_watermark(channel_id, news_id, updated) {
+            log_error("Advancing channel watermark", e);
+        }
+    }
+
+    /// Edit the posts of items that were revised, then archive `news` so searches see it without a new backfill.
+    ///
+    /// The archive holds the versions that were posted, so a revision whose posts couldn't all be edited
+    /// keeps its archived version and is found again next cycle.
+    async fn apply_revisions(&self, subscriptions: &[Subscription], news: &News, shutdown: &CancellationToken) {
+        let mut unapplied = BTreeSet::new();
+        for revision in self.detect_revisions(news) {
+            if shutdown.is_cancelled() || !self.update_deliveries(subscriptions, &revision).await {
+                unapplied.insert(revision.current.get_id());
+            }
+        }
+        let archived: Vec<NewsItem> = news.iter().filter(|item| !unapplied.contains(&item.get_id())).cloned().collect();
+        if let Err(e) = self.store.archive_news(&archived) {
+            log_error("Archiving polled news", e);
+        }
+    }
+
+    /// Items of `news` that changed since they were archived.
+    fn detect_revisions(&self, news: &News) -> Vec<Revision> {
+        news.iter()
+            .filter_map(|item| match self.store.archived_item(item.get_id()) {
+                Ok(previous) => Revision::between(previous?, item),
+                Err(e) => {
+                    log_error("Reading archived news", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Edit every message the revised item was posted as, in channels that want updates.
+    /// Returns `false` if any of them could not be edited.
+    async fn update_deliveries(&self, subscriptions: &[Subscription], revision: &Revision) -> bool {
+        let deliveries = match self.store.deliveries_of(revision.current.get_id()) {
+            Ok(deliveries) => deliveries,
+            Err(e) => {
+                log_error("Reading deliveries of revised news", e);
+                return false;
+            }
+        };
+        let mut edited = true;
+        for (channel_id, message_id) in deliveries {
+            let Some(subscription) = subscriptions.iter().find(|s| s.channel_id == channel_id) else {
+                continue;
+            };
+            let mode = UpdateMode::of(subscription);
+            if mode == UpdateMode::Off {
+                continue;
+            }
+            let platforms = if subscription.platforms.is_empty() { &self.config.default_platforms } else { &subscription.platforms };
+            log_info("Updating revised news", Some(&format!("ID:{} Channel:{} Message:{}", revision.current.get_id(), channel_id, message_id)));
+            edited &= self.edit_post(ChannelId::new(channel_id), MessageId::new(message_id), revision, platforms, mode).await;
+        }
+        edited
+    }
+
+    /// Replace the embed of the revised item in a posted message, leaving any other items' embeds as they are.
+    /// Returns `false` if the message still exists but could not be edited.
+    async fn edit_post(&self, channel: ChannelId, message_id: MessageId, revision: &Revision, channel_platforms: &BTreeSet<String>, mode: UpdateMode) -> bool {
+        let message = match channel.message(&self.http, message_id).await {
+            Ok(message) => message,
+            // The starter message of a forum post lives in the post itself, which shares its ID
+            Err(_) => match ChannelId::new(message_id.get()).message(&self.http, message_id).await {
+                Ok(message) => message,
+                // Deleted posts are left alone, trying again wouldn't bring them back
+                Err(e) if is_not_found(&e) => {
+                    log_info("Revised news message is gone", Some(&format!("Channel:{} Message:{}", channel, message_id)));
+                    return true;
+                },
+                Err(e) => {
+                    log_error(&format!("Reading posted news message {} in channel {}", message_id, channel), e);
+                    return false;
+                }
+            },
+        };
+        let url = revision.current.get_url();
+        let embeds: Vec<CreateEmbed> = message.embeds.iter()
+            .map(|embed| if embed.url.as_deref() == Some(url.as_str()) {
+                revision.embed(embed, channel_platforms, mode)
+            } else {
+                CreateEmbed::from(embed.clone())
+            })
+            .collect();
+        // Without new attachments the edit keeps the original ones, which the footers still point to
+        match message.channel_id.edit_message(&self.http, message.id, EditMessage::new().embeds(embeds)).await {
+            Ok(_) => true,
+            Err(e) => {
+                log_error(&format!("Editing posted news message {} in channel {}", message_id, channel), e);
+                false
+            }
+        }
+    }
+
+    /// News items above the channel's watermark that were not delivered to it yet, oldest first.
+    ///
+    /// At most `max_catch_up` of the newest ones are returned; the rest fall below the watermark once the cycle is over.
+    fn pending_items<'a>(&self, channel_id: u64, news: &'a News) -> Vec<&'a NewsItem> {
+        let Some(watermark) = self.channel_watermark(channel_id) else {
+            return Vec::new();
+        };
+        // Anything dated later is held back until its time comes, so it can't push a watermark past news still to come
+        let latest = Utc::now() + chrono::Duration::seconds(FUTURE_TOLERANCE_SECONDS);
+        let mut pending: Vec<(DateTime<Utc>, &NewsItem)> = news.iter()
+            .filter_map(|item| item.updated_at().map(|updated| (updated, item)))
+            // Equal timestamps are let through, the ledger already tells apart what was posted
+            .filter(|(updated, item)| is_above(&watermark, *updated, item.get_id()) && *updated <= latest && !self.is_delivered(channel_id, item.get_id()))
+            .collect();
+        pending.sort_by_key(|(updated, item)| (*updated, item.get_id()));
+        if pending.len() > self.config.max_catch_up {
+            let skipped = pending.len() - self.config.max_catch_up;
+            log_info("Skipping missed news beyond catch-up limit", Some(&format!("Channel:{} Skipped:{}", channel_id, skipped)));
+            pending.drain(..skipped);
+        }
+        pending.into_iter().map(|(_, item)| item).collect()
+    }
+
+    /// The channel's watermark, starting one at the current time if it has none.
+    fn channel_watermark(&self, channel_id: u64) -> Option<Watermark> {
+        match self.store.watermark(channel_id) {
+            Ok(Some(watermark)) => Some(watermark),
+            Ok(None) => {
+                let now = Utc::now();
+                if let Err(e) = self.store.set_watermark(channel_id, None, now) {
+                    log_error("Starting channel watermark", e);
+                }
+                Some(Watermark { updated_at: now, news_id: None })
+            },
+            Err(e) => {
+                // Err on the side of not posting
+                log_error("Reading channel watermark", e);
+                None
+            }
+        }
+    }
+
+    fn is_delivered(&self, channel_id: u64, news_id: u64) -> bool {
+        match self.store.is_delivered(channel_id, news_id) {
+            Ok(delivered) => delivered,
+            Err(e) => {
+                // Err on the side of not reposting
+                log_error("Checking delivery ledger", e);
+                true
+            }
+        }
+    }
+
+    /// Seed the delivery ledger of a channel that predates it from the news posts still visible in the channel.
+    async fn bootstrap_ledger(&self, channel: ChannelId) {
+        if self.config.msg_count == 0 {
+            return;
+        }
+        match self.store.is_bootstrapped(channel.get()) {
+            Ok(false) => {},
+            Ok(true) => return,
+            Err(e) => {
+                log_error("Checking delivery ledger", e);
+                return;
+            }
+        }
+        let builder = GetMessages::new().limit(self.config.msg_count);
+        match channel.messages(&self.http, builder).await {
+            Ok(existing_messages) => {
+                let existing_ids = get_ids_from_messages(&existing_messages);
+                for (news_id, message_id) in existing_ids.iter() {
+                    if let Err(e) = self.store.record_delivery(channel.get(), *news_id, *message_id) {
+                        log_error("Bootstrapping delivery ledger", e);
+                    }
+                }
+                // Also when nothing was found, so the history isn't fetched again every cycle
+                if let Err(e) = self.store.mark_bootstrapped(channel.get()) {
+                    log_error("Bootstrapping delivery ledger", e);
+                }
+                log_info("Bootstrapped delivery ledger", Some(&format!("Channel:{} Items:{}", channel.get(), existing_ids.len())));
+            },
+            Err(e) => log_error("Reading channel messages for ledger bootstrap", e),
+        }
+    }
+}
+
+/// Whether Discord answered that what was asked for doesn't exist.
+fn is_not_found(error: &serenity::Error) -> bool {
+    matches!(error, serenity::Error::Http(e) if e.status_code() == Some(StatusCode::NOT_FOUND))
+}
+
+/// Whether an item is new to a channel with `watermark`, see [`Watermark`].
+fn is_above(watermark: &Watermark, updated: DateTime<Utc>, news_id: u64) -> bool {
+    updated >= watermark.updated_at || watermark.news_id.is_some_and(|id| news_id > id)
+}
+
+/// Returns `(news ID, message ID)` pairs for news posts found among `messages`.
+fn get_ids_from_messages(messages: &Vec<Message>) -> Vec<(u64, u64)> {
+    let mut result: Vec<(u64, u64)> = vec![];
+    // Regex to find IDs in embed URLs like https://playstartrekonline.com/en/news/article/1234567
+    let re_embed_url = Regex::new(r"playstartrekonline\.com/en/news/article/(\d+)").unwrap();
+    // Original regex for message content (kept as fallback or for other potential ID formats)
+    let re_content = Regex::new(r"ID:(\d+)").unwrap();
+
+    for m in messages {
+        // Check embeds first
+        for embed in &m.embeds {
+            if let Some(url) = &embed.url
+                && let Some(capture) = re_embed_url.captures(url)
+                && let Ok(id) = capture[1].parse::<u64>() {
+                result.push((id, m.id.get()));
+            }
+        }
+
+        // If not found in embed, check content (optional fallback)
+        if let Some(capture) = re_content.captures(m.content.as_str())
+            && let Ok(id) = capture[1].parse::<u64>() {
+            result.push((id, m.id.get()));
+        }
+    }
+    result.sort_unstable();
+    result.dedup_by_key(|(news_id, _)| *news_id);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, ResponseTemplate};
+    use crate::test_support::{news_fixture, news_history, test_poller, MockUpstream};
+
+    #[tokio::test]
+    async fn poll_pipeline_selects_undelivered_items_since_watermark() {
+        let upstream = MockUpstream::start().await;
+        upstream.serve_news(news_fixture()).await;
+        let poller = test_poller(&upstream);
+        let platforms = BTreeSet::from(["pc".to_string()]);
+
+        let news = poller.arc.latest_news(None, 20).await.unwrap().for_platforms(&platforms);
+        // Channels see nothing from before their watermark, which starts at registration
+        assert!(poller.store.watermark(42).unwrap().is_some());
+        poller.store.set_watermark(42, None, Utc::now() + chrono::Duration::minutes(1)).unwrap();
+        assert!(poller.pending_items(42, &news).is_empty());
+
+        // As if the bot was down for a day
+        poller.store.set_watermark(42, None, Utc::now() - chrono::Duration::days(1)).unwrap();
+        let pending: Vec<u64> = poller.pending_items(42, &news).iter().map(|item| item.get_id()).collect();
+        // 11100002 is console only and 11100003 is older than the watermark
+        assert_eq!(pending, vec![11100001]);
+
+        let (embed, icon) = poller.pending_items(42, &news)[0].to_embed(&platforms);
+        let embed = serde_json::to_value(embed).unwrap();
+        assert_eq!(icon.map(|i| i.filename), Some("windows.png"));
+        assert_eq!(embed["footer"]["icon_url"], "attachment://windows.png");
+        assert_eq!(embed["title"], "Star Trek Online: Featured Episode Event");
+        assert_eq!(embed["url"], "https://playstartrekonline.com/en/news/article/11100001");
+        assert_eq!(embed["thumbnail"]["url"], "https://example.invalid/thumbnails/11100001.jpg");
+
+        let store_only = BTreeSet::from(["store".to_string()]);
+        assert!(poller.pending_items(42, &news.for_categories(&store_only)).is_empty());
+
+        poller.record_delivery(42, poller.pending_items(42, &news)[0], 1);
+        assert!(poller.pending_items(42, &news).is_empty());
+    }
+
+    #[tokio::test]
+    async fn catches_up_on_missed_news_with_the_defaults() {
+        let upstream = MockUpstream::start().await;
+        upstream.serve_news_pages(&news_history(DEFAULT_MAX_CATCH_UP as u64 + 5)).await;
+        let poller = test_poller(&upstream);
+        let subscriptions = poller.store.list_subscriptions().unwrap();
+
+        // Everything on the first page is newer than the channel's mark, so it may have missed more
+        poller.store.set_watermark(42, None, Utc::now() - chrono::Duration::days(1)).unwrap();
+        let news = poller.fetch_news(&subscriptions).await.unwrap();
+        assert_eq!(news.len(), DEFAULT_MAX_CATCH_UP);
+        assert_eq!(poller.metrics.upstream_requests(), 1 + DEFAULT_MAX_CATCH_UP as u64 / PAGE_SIZE as u64);
+
+        // A channel that saw the first page's oldest item only needs that page
+        poller.store.set_watermark(42, None, Utc::now() + chrono::Duration::minutes(1)).unwrap();
+        assert_eq!(poller.fetch_news(&subscriptions).await.unwrap().len(), DEFAULT_POLL_COUNT as usize);
+    }
+
+    #[tokio::test]
+    async fn retries_revisions_until_posts_are_edited() {
+        let upstream = MockUpstream::start().await;
+        upstream.serve_news(news_fixture()).await;
+        let message = serde_json::json!({
+            "id": "1", "channel_id": "42", "content": "", "timestamp": "2024-01-01T00:00:00+00:00", "edited_timestamp": null,
+            "author": {"id": "7", "username": "stobot", "discriminator": "0", "avatar": null, "bot": true},
+            "tts": false, "mention_everyone": false, "mentions": [], "mention_roles": [], "attachments": [], "pinned": false, "type": 0,
+            "embeds": [{"title": "Old Title", "url": "https://playstartrekonline.com/en/news/article/11100001"}],
+        });
+        let message_path = "/api/v10/channels/42/messages/1";
+        Mock::given(method("GET")).and(path(message_path))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&message))
+            .mount(upstream.server()).await;
+        // Discord fails the first edit
+        Mock::given(method("PATCH")).and(path(message_path))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .mount(upstream.server()).await;
+        Mock::given(method("PATCH")).and(path(message_path))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&message))
+            .expect(1)
+            .mount(upstream.server()).await;
+        let poller = test_poller(&upstream);
+        let platforms = BTreeSet::from(["pc".to_string()]);
+        let news = poller.arc.latest_news(None, 20).await.unwrap();
+        let posted = NewsItem::from_archive(11100001, "Old Title".to_string(), String::new(), platforms.clone(), "2021-03-05 10:00:00".to_string(), None, Vec::new());
+        poller.store.archive_news(&[posted]).unwrap();
+        poller.store.record_delivery(42, 11100001, 1).unwrap();
+        let subscriptions = poller.store.list_subscriptions().unwrap();
+        let archived_title = || poller.store.archived_item(11100001).unwrap().unwrap().get_title().to_string();
+
+        // The posted version stays archived until the post shows the revision
+        poller.apply_revisions(&subscriptions, &news, &CancellationToken::new()).await;
+        assert_eq!(archived_title(), "Old Title");
+        assert!(poller.store.archived_item(11100002).unwrap().is_some());
+        poller.apply_revisions(&subscriptions, &news, &CancellationToken::new()).await;
+        assert_eq!(archived_title(), "Star Trek Online: Featured Episode Event");
+        // Nothing left to edit
+        poller.apply_revisions(&subscriptions, &news, &CancellationToken::new()).await;
+    }
+
+    #[tokio::test]
+    async fn watermark_follows_evaluated_news() {
+        let upstream = MockUpstream::start().await;
+        upstream.serve_news(news_fixture()).await;
+        let poller = test_poller(&upstream);
+        let platforms = BTreeSet::from(["pc".to_string()]);
+        let news = poller.arc.latest_news(None, 20).await.unwrap();
+        let newest = news.iter().filter_map(|item| item.updated_at()).max().unwrap();
+        let day_ago = Utc::now() - chrono::Duration::days(1);
+        poller.store.set_watermark(42, None, day_ago).unwrap();
+
+        // What failed to post stays above the mark
+        let channel_news = news.for_platforms(&platforms);
+        let pending = poller.pending_items(42, &channel_news);
+        poller.advance_watermark(42, &news, &pending);
+        assert_eq!(poller.store.watermark(42).unwrap().unwrap(), Watermark { updated_at: newest, news_id: None });
+        assert_eq!(poller.pending_items(42, &channel_news).len(), 1);
+
+        // Nothing posted for the channel: the mark still moves past what was looked at, matched or not
+        poller.advance_watermark(42, &news, &[]);
+        assert_eq!(poller.store.watermark(42).unwrap().unwrap(), Watermark { updated_at: newest, news_id: Some(11100003) });
+        // Only items dated at the mark itself are let through, the ledger tells those apart
+        assert!(poller.pending_items(42, &news).iter().all(|item| item.updated_at() == Some(newest)));
+
+        // An article published late with an older date is still new to the channel, once
+        let late = News::from_items(vec![NewsItem::from_archive(
+            11100004, "Late Article".to_string(), String::new(), platforms.clone(), "2021-03-05 10:00:00".to_string(), None, Vec::new(),
+        )]);
+        assert_eq!(poller.pending_items(42, &late).iter().map(|item| item.get_id()).collect::<Vec<_>>(), vec![11100004]);
+        poller.advance_watermark(42, &late, &poller.pending_items(42, &late));
+        assert_eq!(poller.pending_items(42, &late).len(), 1);
+        poller.advance_watermark(42, &late, &[]);
+        assert!(poller.pending_items(42, &late).is_empty());
+        assert_eq!(poller.store.watermark(42).unwrap().unwrap().updated_at, newest);
+    }
+}
@@ -0,0 +1,149 @@
+// Revisions of news items that were already posted, and how posted messages are brought up to date
+use std::collections::BTreeSet;
+use serenity::builder::{CreateEmbed, CreateEmbedFooter};
+use serenity::model::channel::Embed;
+
+use crate::markdown::{truncate, EMBED_FIELD_VALUE_LIMIT};
+use crate::news::NewsItem;
+use crate::store::Subscription;
+
+/// Key of the channel setting holding the [`UpdateMode`].
+pub const UPDATE_MODE_SETTING: &str = "update_mode";
+
+/// Longest text kept of each side of a change, so the marker stays short.
+const CHANGE_EXCERPT_LIMIT: usize = 120;
+
+/// What happens to posted news when ARC revises it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateMode {
+    /// Posts are left as they are
+    Off,
+    /// Posts are edited to show the current version
+    Edit,
+    /// Posts are edited, with a field saying what changed
+    Marker,
+}
+
+impl UpdateMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            UpdateMode::Off => "off",
+            UpdateMode::Edit => "edit",
+            UpdateMode::Marker => "marker",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<UpdateMode> {
+        match name.trim().to_lowercase().as_str() {
+            "off" => Some(UpdateMode::Off),
+            "edit" => Some(UpdateMode::Edit),
+            "marker" => Some(UpdateMode::Marker),
+            _ => None,
+        }
+    }
+
+    pub fn of(subscription: &Subscription) -> UpdateMode {
+        subscription.settings.get(UPDATE_MODE_SETTING)
+            .and_then(|mode| UpdateMode::from_name(mode))
+            .unwrap_or(UpdateMode::Edit)
+    }
+}
+
+/// A news item whose title, summary or update time changed since it was archived.
+#[derive(Debug, Clone)]
+pub struct Revision {
+    pub previous: NewsItem,
+    pub current: NewsItem,
+}
+
+impl Revision {
+    /// The revision from `previous` to `current`, or `None` if nothing that is posted changed.
+    pub fn between(previous: NewsItem, current: &NewsItem) -> Option<Revision> {
+        let changed = previous.get_title() != current.get_title()
+            || previous.get_summary() != current.get_summary()
+            || previous.get_updated() != current.get_updated();
+        changed.then(|| Revision { previous, current: current.clone() })
+    }
+
+    /// One line per change, for the "Updated" marker.
+    pub fn changes(&self) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.previous.get_title() != self.current.get_title() {
+            changes.push(format!("**Title:** {}", word_diff(self.previous.get_title(), self.current.get_title())));
+        }
+        if self.previous.get_summary() != self.current.get_summary() {
+            changes.push(format!("**Summary:** {}", word_diff(self.previous.get_summary(), self.current.get_summary())));
+        }
+        match self.current.updated_at() {
+            Some(updated) => changes.push(format!("Revised <t:{}:f>", updated.timestamp())),
+            None => changes.push(format!("Revised {}", self.current.get_updated())),
+        }
+        changes
+    }
+
+    /// The current version of the embed `posted`, which showed the previous one.
+    ///
+    /// The footer is carried over as posted, since its icon was resolved from an attachment of the original message.
+    pub fn embed(&self, posted: &Embed, channel_platforms: &BTreeSet<String>, mode: UpdateMode) -> CreateEmbed {
+        let mut embed = self.current.to_plain_embed(channel_platforms);
+        if let Some(footer) = &posted.footer {
+            let mut carried = CreateEmbedFooter::new(&footer.text);
+            if let Some(icon_url) = &footer.icon_url {
+                carried = carried.icon_url(icon_url);
+            }
+            embed = embed.footer(carried);
+        }
+        if mode == UpdateMode::Marker {
+            embed = embed.field("📝 Updated", truncate(&self.changes().join("\n"), EMBED_FIELD_VALUE_LIMIT), false);
+        }
+        embed
+    }
+}
+
+/// The differing middle of two texts, compared word by word, as `~~removed~~ → added`.
+fn word_diff(old: &str, new: &str) -> String {
+    let old_words: Vec<&str> = old.split_whitespace().collect();
+    let new_words: Vec<&str> = new.split_whitespace().collect();
+    let prefix = old_words.iter().zip(new_words.iter()).take_while(|(a, b)| a == b).count();
+    let suffix = old_words[prefix..].iter().rev()
+        .zip(new_words[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let removed = truncate(&old_words[prefix..old_words.len() - suffix].join(" "), CHANGE_EXCERPT_LIMIT);
+    let added = truncate(&new_words[prefix..new_words.len() - suffix].join(" "), CHANGE_EXCERPT_LIMIT);
+    match (removed.is_empty(), added.is_empty()) {
+        (true, true) => "whitespace only".to_string(),
+        (true, false) => format!("added “{}”", added),
+        (false, true) => format!("removed ~~{}~~", removed),
+        (false, false) => format!("~~{}~~ → {}", removed, added),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::news::News;
+    use crate::test_support::news_fixture;
+
+    #[test]
+    fn describes_what_changed() {
+        let news: News = serde_json::from_str(&news_fixture()).unwrap();
+        let item = news.iter().find(|item| item.get_id() == 11100002).unwrap();
+        assert!(Revision::between(item.clone(), item).is_none());
+
+        let revised = NewsItem::from_archive(
+            item.get_id(),
+            item.get_title().replace("Today", "Tomorrow"),
+            format!("{} Also fixed tribbles.", item.get_summary()),
+            item.get_platforms().clone(),
+            item.get_updated().to_string(),
+            item.get_thumbnail_url().map(str::to_string),
+            item.get_tags().to_vec(),
+        );
+        let revision = Revision::between(item.clone(), &revised).unwrap();
+        let changes = revision.changes();
+        assert_eq!(changes[0], "**Title:** ~~Today~~ → Tomorrow");
+        assert_eq!(changes[1], "**Summary:** added “Also fixed tribbles.”");
+        assert!(changes[2].starts_with("Revised <t:"));
+    }
+}
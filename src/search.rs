@@ -0,0 +1,222 @@
+use std::collections::BTreeSet;
+use chrono::{DateTime, NaiveDate, NaiveTime, TimeZone, Utc};
+use serenity::futures::StreamExt;
+
+use crate::arc_api::{ArcApiError, ArcClient, NewsQuery, NEWS_FIELDS, PAGE_SIZE};
+use crate::category::Category;
+use crate::logging::log_error;
+use crate::news::NewsItem;
+use crate::platform::Platform;
+use crate::store::{BackfillState, NewsArchive};
+
+/// Pages of history walked per search, to bound the load a single command puts on the API.
+pub const MAX_SEARCH_PAGES: usize = 25;
+
+/// Most results returned by a search.
+pub const MAX_SEARCH_RESULTS: usize = 50;
+
+/// Full-text matches taken from the archive before platform and category are checked.
+const MAX_ARCHIVE_CANDIDATES: usize = 500;
+
+/// A news search as typed into `/stobot_search`.
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    /// Lowercase terms that must all start a word of the title or summary
+    pub terms: Vec<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub platform: Option<Platform>,
+    pub category: Option<Category>,
+}
+
+impl SearchQuery {
+    /// Build a query from the raw command options, with dates as `YYYY-MM-DD`.
+    pub fn parse(text: &str, since: Option<&str>, until: Option<&str>, platform: Option<&str>, category: Option<&str>) -> Result<SearchQuery, String> {
+        let terms: Vec<String> = text.split_whitespace().map(str::to_lowercase).collect();
+        if terms.is_empty() {
+            return Err("Please provide something to search for.".to_string());
+        }
+        let since = since.map(|d| parse_date(d, NaiveTime::MIN)).transpose()?;
+        // `until` includes the whole day
+        let until = until.map(|d| parse_date(d, NaiveTime::from_hms_opt(23, 59, 59).unwrap())).transpose()?;
+        if let (Some(since), Some(until)) = (since, until)
+            && since > until {
+            return Err("`since` must not be after `until`.".to_string());
+        }
+        Ok(SearchQuery {
+            terms,
+            since,
+            until,
+            platform: platform.map(str::parse).transpose()?,
+            category: category.map(str::parse).transpose()?,
+        })
+    }
+
+    /// Relevance of `item`, or `None` if it doesn't match. Title hits count three times as much as summary hits.
+    pub fn score(&self, item: &NewsItem) -> Option<usize> {
+        if let Some(platform) = self.platform
+            && item.matching_platforms(&BTreeSet::from([platform.api_name().to_string()])).is_empty() {
+            return None;
+        }
+        if let Some(category) = self.category
+            && !item.categories().contains(&category) {
+            return None;
+        }
+        if self.since.is_some() || self.until.is_some() {
+            let updated = item.updated_at()?;
+            if self.since.is_some_and(|since| updated < since) || self.until.is_some_and(|until| updated > until) {
+                return None;
+            }
+        }
+        let title = words(item.get_title());
+        let summary = words(item.get_summary());
+        let mut score = 0;
+        let mut searched = false;
+        for term in self.terms.iter() {
+            // Terms of punctuation only are skipped, as in the archive's full-text search
+            let term = words(term);
+            if term.is_empty() {
+                continue;
+            }
+            searched = true;
+            let hits = prefix_hits(&title, &term) * 3 + prefix_hits(&summary, &term);
+            if hits == 0 {
+                return None;
+            }
+            score += hits;
+        }
+        searched.then_some(score)
+    }
+
+    /// The matching items of `items`, best first and newest first among equals.
+    pub fn rank<'a>(&self, items: impl IntoIterator<Item = &'a NewsItem>) -> Vec<NewsItem> {
+        let mut matches: Vec<(usize, &NewsItem)> = items.into_iter()
+            .filter_map(|item| self.score(item).map(|score| (score, item)))
+            .collect();
+        matches.sort_by(|(a_score, a), (b_score, b)| b_score.cmp(a_score).then_with(|| b.updated_at().cmp(&a.updated_at())));
+        matches.into_iter().take(MAX_SEARCH_RESULTS).map(|(_, item)| item.clone()).collect()
+    }
+}
+
+/// Lowercase words of `text`, split on everything but letters and digits like the archive's full-text index.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// How often the words of `term` appear in a row in `words`, the last one as a prefix, like an FTS5 `"term"*` query.
+fn prefix_hits(words: &[String], term: &[String]) -> usize {
+    let Some((last, leading)) = term.split_last() else {
+        return 0;
+    };
+    words.windows(term.len())
+        .filter(|window| window[..leading.len()] == *leading && window[leading.len()].starts_with(last.as_str()))
+        .count()
+}
+
+fn parse_date(date: &str, time: NaiveTime) -> Result<DateTime<Utc>, String> {
+    NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .map(|d| Utc.from_utc_datetime(&d.and_time(time)))
+        .map_err(|_| format!("`{}` is not a date, use the form 2024-01-31.", date.trim()))
+}
+
+/// Rank everything matching `query`, from the local archive once it holds the whole history and from the API until then.
+///
+/// The API is only a fallback for a bot whose backfill never finished, whatever it returns is archived for next time.
+/// Both match the same way, so a search finds the same items either way.
+pub async fn search_news(arc: &ArcClient, archive: &dyn NewsArchive, query: &SearchQuery) -> Result<Vec<NewsItem>, ArcApiError> {
+    match archive.backfill_state() {
+        Ok(BackfillState::Complete) => match archive.search_archive(query, MAX_ARCHIVE_CANDIDATES) {
+            Ok(candidates) => return Ok(query.rank(candidates.iter())),
+            Err(e) => log_error("Searching the news archive", e),
+        },
+        Ok(BackfillState::Partial(_)) => {},
+        Err(e) => log_error("Reading the backfill state", e),
+    }
+    let seen = walk_news(arc, query).await?;
+    if let Err(e) = archive.archive_news(&seen) {
+        log_error("Archiving searched news", e);
+    }
+    Ok(query.rank(seen.iter()))
+}
+
+/// Walk the news history newest first, collecting everything that may match `query`.
+///
+/// Stops once the pages are older than `query.since`, or after [`MAX_SEARCH_PAGES`] pages.
+async fn walk_news(arc: &ArcClient, query: &SearchQuery) -> Result<Vec<NewsItem>, ArcApiError> {
+    let api_query = NewsQuery {
+        limit: Some(PAGE_SIZE),
+        offset: Some(0),
+        platform: query.platform.map(|p| p.api_name()),
+        fields: NEWS_FIELDS,
+        ..NewsQuery::default()
+    };
+    let mut seen = Vec::new();
+    let mut pages = Box::pin(arc.news_pages(api_query).take(MAX_SEARCH_PAGES));
+    while let Some(page) = pages.next().await {
+        let page = page?;
+        let reached_since = query.since.is_some_and(|since| page.iter().all(|item| item.updated_at().is_some_and(|u| u < since)));
+        seen.extend(page.iter().cloned());
+        if reached_since {
+            break;
+        }
+    }
+    Ok(seen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::news::News;
+    use crate::store::NewsArchive;
+    use crate::test_support::{memory_store, news_fixture, MockUpstream};
+
+    #[test]
+    fn matches_terms_and_filters() {
+        let news: News = serde_json::from_str(&news_fixture()).unwrap();
+        let ids = |query: SearchQuery| query.rank(news.iter()).iter().map(|item| item.get_id()).collect::<Vec<_>>();
+
+        // "patch" is in the title of 11100002 only, "fail" in its summary
+        assert_eq!(ids(SearchQuery::parse("patch FAIL", None, None, None, None).unwrap()), vec![11100002]);
+        // A term starting a word of every item matches all of them, one inside a word doesn't
+        assert_eq!(ids(SearchQuery::parse("s", None, None, None, None).unwrap()).len(), 3);
+        assert!(ids(SearchQuery::parse("box", None, None, None, None).unwrap()).is_empty());
+        assert_eq!(ids(SearchQuery::parse("sale", None, None, None, None).unwrap()), vec![11100003]);
+        assert!(ids(SearchQuery::parse("sale", Some("2022-01-01"), None, None, None).unwrap()).is_empty());
+        assert!(ids(SearchQuery::parse("patch", None, None, Some("pc"), None).unwrap()).is_empty());
+        assert_eq!(ids(SearchQuery::parse("a", None, None, None, Some("store")).unwrap()), vec![11100003]);
+        assert!(SearchQuery::parse("sale", Some("yesterday"), None, None, None).is_err());
+        assert!(SearchQuery::parse("sale", Some("2024-02-01"), Some("2024-01-01"), None, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn archive_and_api_find_the_same_items() {
+        let upstream = MockUpstream::start().await;
+        upstream.serve_news(news_fixture()).await;
+        let client = ArcClient::new(&upstream.api_url(), Duration::from_secs(5), 0).unwrap();
+        // Nothing is served here, so the archive has to answer on its own
+        let offline = MockUpstream::start().await;
+        let offline_client = ArcClient::new(&offline.api_url(), Duration::from_secs(5), 0).unwrap();
+        let store = memory_store();
+        let queries = ["box", "lockbox", "LOCKBOXES sale", "patch fail", "enterprise-f", "u.s.s", "s", "\"-\""];
+        let ids = |results: Vec<NewsItem>| results.iter().map(|item| item.get_id()).collect::<Vec<_>>();
+
+        let mut from_api = Vec::new();
+        for text in queries {
+            let query = SearchQuery::parse(text, None, None, None, None).unwrap();
+            from_api.push(ids(search_news(&client, &store, &query).await.unwrap()));
+        }
+        store.set_backfill_state(BackfillState::Complete).unwrap();
+        for (text, expected) in queries.iter().zip(from_api) {
+            let query = SearchQuery::parse(text, None, None, None, None).unwrap();
+            assert_eq!(ids(search_news(&offline_client, &store, &query).await.unwrap()), expected, "query {:?}", text);
+        }
+        let query = SearchQuery::parse("lockbox", None, None, None, None).unwrap();
+        assert_eq!(ids(search_news(&offline_client, &store, &query).await.unwrap()), vec![11100003]);
+        let query = SearchQuery::parse("box", None, None, None, None).unwrap();
+        assert!(search_news(&offline_client, &store, &query).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,915 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::sync::Mutex;
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+
+use crate::filter::{FilterMode, FilterRule};
+use crate::news::NewsItem;
+use crate::ping::{PingRule, PingTarget};
+use crate::platform::Platform;
+use crate::search::SearchQuery;
+
+/// A channel registered to receive news, what it follows and who gets notified.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subscription {
+    pub channel_id: u64,
+    pub guild_id: Option<u64>,
+    pub platforms: BTreeSet<String>,
+    /// Category names, an empty set means every category
+    pub categories: BTreeSet<String>,
+    pub filters: Vec<FilterRule>,
+    pub pings: Vec<PingRule>,
+    /// Free-form per-channel options, such as the post mode
+    pub settings: BTreeMap<String, String>,
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    Sqlite(rusqlite::Error),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Sqlite(e) => write!(f, "SQLite error: {}", e),
+            StoreError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        StoreError::Sqlite(e)
+    }
+}
+
+impl From<std::io::Error> for StoreError {
+    fn from(e: std::io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+/// Persistent storage for channel subscriptions.
+///
+/// Every mutating call is atomic: either the whole change is persisted or none of it is.
+pub trait SubscriptionStore: Send + Sync {
+    fn list_subscriptions(&self) -> Result<Vec<Subscription>, StoreError>;
+    fn get_subscription(&self, channel_id: u64) -> Result<Option<Subscription>, StoreError>;
+    /// Store the subscription of a channel that isn't subscribed yet. Returns `false` if it was, its configuration
+    /// is then left as is and only a guild that wasn't known is filled in.
+    fn insert_subscription(&self, subscription: &Subscription) -> Result<bool, StoreError>;
+    /// Returns `false` if the channel was not subscribed.
+    fn remove_subscription(&self, channel_id: u64) -> Result<bool, StoreError>;
+    /// Returns `false` if the channel was not subscribed.
+    fn set_platforms(&self, channel_id: u64, platforms: &BTreeSet<String>) -> Result<bool, StoreError>;
+    /// Returns `false` if the channel was not subscribed.
+    fn set_categories(&self, channel_id: u64, categories: &BTreeSet<String>) -> Result<bool, StoreError>;
+    /// Returns `false` if the channel was not subscribed.
+    fn set_filters(&self, channel_id: u64, filters: &[FilterRule]) -> Result<bool, StoreError>;
+    /// Returns `false` if the channel was not subscribed.
+    fn set_pings(&self, channel_id: u64, pings: &[PingRule]) -> Result<bool, StoreError>;
+    /// Set a channel setting, or remove it if `value` is `None`. Returns `false` if the channel was not subscribed.
+    fn set_setting(&self, channel_id: u64, key: &str, value: Option<&str>) -> Result<bool, StoreError>;
+}
+
+/// Record of news items already posted, so they are never posted to the same channel twice.
+pub trait DeliveryLedger: Send + Sync {
+    fn is_delivered(&self, channel_id: u64, news_id: u64) -> Result<bool, StoreError>;
+    /// Whether the channel's history was scanned for posts that predate the ledger.
+    fn is_bootstrapped(&self, channel_id: u64) -> Result<bool, StoreError>;
+    /// Remember that the channel's history was scanned, whether or not it held earlier posts.
+    fn mark_bootstrapped(&self, channel_id: u64) -> Result<(), StoreError>;
+    fn record_delivery(&self, channel_id: u64, news_id: u64, message_id: u64) -> Result<(), StoreError>;
+    /// Every `(channel ID, message ID)` the item was posted as.
+    fn deliveries_of(&self, news_id: u64) -> Result<Vec<(u64, u64)>, StoreError>;
+    /// How far the channel has seen the news, see [`Watermark`].
+    fn watermark(&self, channel_id: u64) -> Result<Option<Watermark>, StoreError>;
+    /// Replace the watermark of the channel, `news_id` being the highest news ID it has seen, if any.
+    fn set_watermark(&self, channel_id: u64, news_id: Option<u64>, updated: DateTime<Utc>) -> Result<(), StoreError>;
+}
+
+/// Local copy of the news history, so it can be searched without walking the API.
+pub trait NewsArchive: Send + Sync {
+    /// Insert `items`, or refresh them if already archived. Returns how many were not archived before.
+    fn archive_news(&self, items: &[NewsItem]) -> Result<usize, StoreError>;
+    fn archived_count(&self) -> Result<usize, StoreError>;
+    fn archived_item(&self, news_id: u64) -> Result<Option<NewsItem>, StoreError>;
+    /// Archived items with every term of `query` as a word prefix in their title or summary, within its dates,
+    /// best full-text matches first. Platform and category are left to [`SearchQuery::rank`].
+    fn search_archive(&self, query: &SearchQuery, limit: usize) -> Result<Vec<NewsItem>, StoreError>;
+    fn backfill_state(&self) -> Result<BackfillState, StoreError>;
+    fn set_backfill_state(&self, state: BackfillState) -> Result<(), StoreError>;
+}
+
+/// Everything the bot persists, as a single object to share between tasks.
+pub trait Store: SubscriptionStore + DeliveryLedger + NewsArchive {}
+
+impl<T: SubscriptionStore + DeliveryLedger + NewsArchive> Store for T {}
+
+// Each entry upgrades the schema by one version; the index + 1 is stored in `PRAGMA user_version`.
+// Never edit an entry that has shipped, append a new one instead.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE channels (
+        channel_id INTEGER PRIMARY KEY,
+        guild_id INTEGER,
+        created_at TEXT NOT NULL
+    );
+    CREATE TABLE channel_platforms (
+        channel_id INTEGER NOT NULL REFERENCES channels(channel_id) ON DELETE CASCADE,
+        platform TEXT NOT NULL,
+        PRIMARY KEY (channel_id, platform)
+    );
+    CREATE TABLE channel_settings (
+        channel_id INTEGER NOT NULL REFERENCES channels(channel_id) ON DELETE CASCADE,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (channel_id, key)
+    );
+    CREATE TABLE meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );",
+    "CREATE TABLE deliveries (
+        channel_id INTEGER NOT NULL,
+        news_id INTEGER NOT NULL,
+        message_id INTEGER NOT NULL,
+        delivered_at TEXT NOT NULL,
+        PRIMARY KEY (channel_id, news_id)
+    );",
+    "CREATE TABLE channel_categories (
+        channel_id INTEGER NOT NULL REFERENCES channels(channel_id) ON DELETE CASCADE,
+        category TEXT NOT NULL,
+        PRIMARY KEY (channel_id, category)
+    );",
+    "CREATE TABLE channel_filters (
+        channel_id INTEGER NOT NULL REFERENCES channels(channel_id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        mode TEXT NOT NULL,
+        pattern TEXT NOT NULL,
+        is_regex INTEGER NOT NULL,
+        PRIMARY KEY (channel_id, position)
+    );",
+    "CREATE TABLE channel_pings (
+        channel_id INTEGER NOT NULL REFERENCES channels(channel_id) ON DELETE CASCADE,
+        target TEXT NOT NULL,
+        categories TEXT NOT NULL,
+        platforms TEXT NOT NULL,
+        PRIMARY KEY (channel_id, target)
+    );",
+    // `updated_at` is the UTC form of `updated`, so dates can be compared as text
+    "CREATE TABLE news_archive (
+        id INTEGER PRIMARY KEY,
+        title TEXT NOT NULL,
+        summary TEXT NOT NULL,
+        platforms TEXT NOT NULL,
+        updated TEXT NOT NULL,
+        updated_at TEXT,
+        thumbnail TEXT,
+        tags TEXT NOT NULL,
+        archived_at TEXT NOT NULL
+    );
+    CREATE INDEX news_archive_updated_at ON news_archive(updated_at);
+    CREATE VIRTUAL TABLE news_fts USING fts5(
+        title, summary, content='news_archive', content_rowid='id', tokenize='unicode61 remove_diacritics 2'
+    );
+    CREATE TRIGGER news_archive_ai AFTER INSERT ON news_archive BEGIN
+        INSERT INTO news_fts(rowid, title, summary) VALUES (new.id, new.title, new.summary);
+    END;
+    CREATE TRIGGER news_archive_ad AFTER DELETE ON news_archive BEGIN
+        INSERT INTO news_fts(news_fts, rowid, title, summary) VALUES ('delete', old.id, old.title, old.summary);
+    END;
+    CREATE TRIGGER news_archive_au AFTER UPDATE ON news_archive BEGIN
+        INSERT INTO news_fts(news_fts, rowid, title, summary) VALUES ('delete', old.id, old.title, old.summary);
+        INSERT INTO news_fts(rowid, title, summary) VALUES (new.id, new.title, new.summary);
+    END;",
+    // Channels that exist at upgrade time have seen everything up to now
+    "CREATE TABLE channel_watermarks (
+        channel_id INTEGER PRIMARY KEY REFERENCES channels(channel_id) ON DELETE CASCADE,
+        news_id INTEGER,
+        updated_at TEXT NOT NULL
+    );
+    INSERT INTO channel_watermarks (channel_id, updated_at)
+        SELECT channel_id, strftime('%Y-%m-%dT%H:%M:%SZ', 'now') FROM channels;",
+    // Like deliveries, this outlives the subscription, so re-registering doesn't scan the channel again.
+    // Channels with deliveries were scanned before this was tracked.
+    "CREATE TABLE ledger_bootstraps (
+        channel_id INTEGER PRIMARY KEY,
+        bootstrapped_at TEXT NOT NULL
+    );
+    INSERT INTO ledger_bootstraps (channel_id, bootstrapped_at)
+        SELECT DISTINCT channel_id, strftime('%Y-%m-%dT%H:%M:%SZ', 'now') FROM deliveries;",
+];
+
+/// How far a channel has seen the news.
+///
+/// News is new to the channel if it was updated since `updated_at` or has a higher ID than `news_id`.
+/// IDs of new articles only grow, so the latter catches articles published late with an older date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watermark {
+    /// Update time of the newest item the poller evaluated for the channel, or of its registration
+    pub updated_at: DateTime<Utc>,
+    /// Highest news ID the poller evaluated for the channel, none before its first poll
+    pub news_id: Option<u64>,
+}
+
+const LEGACY_IMPORT_KEY: &str = "legacy_channels_imported";
+const BACKFILL_OFFSET_KEY: &str = "backfill_offset";
+const BACKFILL_COMPLETE_KEY: &str = "backfill_complete";
+
+/// How far `stobot backfill` got through the news history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackfillState {
+    /// The history is archived up to this API offset, older items may be missing
+    Partial(u32),
+    /// The whole history was archived at least once
+    Complete,
+}
+
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<SqliteStore, StoreError> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    fn from_connection(mut conn: Connection) -> Result<SqliteStore, StoreError> {
+        conn.pragma_update(None, "foreign_keys", true)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Self::migrate(&mut conn)?;
+        Ok(SqliteStore { conn: Mutex::new(conn) })
+    }
+
+    fn migrate(conn: &mut Connection) -> Result<(), StoreError> {
+        let current: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        for (version, sql) in MIGRATIONS.iter().enumerate().skip(current) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(sql)?;
+            tx.pragma_update(None, "user_version", version + 1)?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    /// One-time import of the legacy `channel:<id>|<platforms>` file.
+    ///
+    /// Returns the number of imported channels, or `None` if the import already happened or there was no file.
+    pub fn import_legacy_channels(&self, path: &str, default_platforms: &BTreeSet<String>) -> Result<Option<usize>, StoreError> {
+        let mut conn = self.conn.lock().unwrap();
+        let already_imported: Option<String> = conn
+            .query_row("SELECT value FROM meta WHERE key = ?1", params![LEGACY_IMPORT_KEY], |row| row.get(0))
+            .optional()?;
+        if already_imported.is_some() {
+            return Ok(None);
+        }
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let subscriptions = parse_legacy_channels(BufReader::new(file), default_platforms)?;
+
+        let tx = conn.transaction()?;
+        for subscription in subscriptions.iter() {
+            upsert(&tx, subscription)?;
+        }
+        tx.execute(
+            "INSERT INTO meta (key, value) VALUES (?1, ?2)",
+            params![LEGACY_IMPORT_KEY, Utc::now().to_rfc3339()],
+        )?;
+        tx.commit()?;
+        Ok(Some(subscriptions.len()))
+    }
+}
+
+/// Parse lines of the form `channel:123456789|pc,ps,xbox`, ignoring anything malformed and unknown platforms.
+fn parse_legacy_channels(reader: impl BufRead, default_platforms: &BTreeSet<String>) -> Result<Vec<Subscription>, StoreError> {
+    let mut subscriptions = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let Some(entry) = line.strip_prefix("channel:") else {
+            continue;
+        };
+        let Some((id, platforms)) = entry.split_once('|') else {
+            continue;
+        };
+        let Ok(channel_id) = id.trim().parse::<u64>() else {
+            continue;
+        };
+        // Older files may hold names like `Playstation` that never matched the API, normalise them
+        let platforms: BTreeSet<String> = platforms.split(',')
+            .filter_map(Platform::from_alias)
+            .map(|p| p.api_name().to_string())
+            .collect();
+        subscriptions.push(Subscription {
+            channel_id,
+            guild_id: None,
+            platforms: if platforms.is_empty() { default_platforms.clone() } else { platforms },
+            categories: BTreeSet::new(),
+            filters: Vec::new(),
+            pings: Vec::new(),
+            settings: BTreeMap::new(),
+        });
+    }
+    Ok(subscriptions)
+}
+
+fn upsert(conn: &Connection, subscription: &Subscription) -> Result<(), StoreError> {
+    conn.execute(
+        "INSERT INTO channels (channel_id, guild_id, created_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT(channel_id) DO UPDATE SET guild_id = COALESCE(excluded.guild_id, channels.guild_id)",
+        params![subscription.channel_id as i64, subscription.guild_id.map(|id| id as i64), Utc::now().to_rfc3339()],
+    )?;
+    // A new channel only gets news from its registration on, re-registering keeps the old mark
+    conn.execute(
+        "INSERT OR IGNORE INTO channel_watermarks (channel_id, updated_at) VALUES (?1, ?2)",
+        params![subscription.channel_id as i64, archive_timestamp(Utc::now())],
+    )?;
+    replace_platforms(conn, subscription.channel_id, &subscription.platforms)?;
+    replace_categories(conn, subscription.channel_id, &subscription.categories)?;
+    replace_filters(conn, subscription.channel_id, &subscription.filters)?;
+    replace_pings(conn, subscription.channel_id, &subscription.pings)?;
+    conn.execute("DELETE FROM channel_settings WHERE channel_id = ?1", params![subscription.channel_id as i64])?;
+    for (key, value) in subscription.settings.iter() {
+        write_setting(conn, subscription.channel_id, key, Some(value))?;
+    }
+    Ok(())
+}
+
+fn replace_platforms(conn: &Connection, channel_id: u64, platforms: &BTreeSet<String>) -> Result<(), StoreError> {
+    conn.execute("DELETE FROM channel_platforms WHERE channel_id = ?1", params![channel_id as i64])?;
+    let mut insert = conn.prepare("INSERT INTO channel_platforms (channel_id, platform) VALUES (?1, ?2)")?;
+    for platform in platforms {
+        insert.execute(params![channel_id as i64, platform])?;
+    }
+    Ok(())
+}
+
+fn replace_categories(conn: &Connection, channel_id: u64, categories: &BTreeSet<String>) -> Result<(), StoreError> {
+    conn.execute("DELETE FROM channel_categories WHERE channel_id = ?1", params![channel_id as i64])?;
+    let mut insert = conn.prepare("INSERT INTO channel_categories (channel_id, category) VALUES (?1, ?2)")?;
+    for category in categories {
+        insert.execute(params![channel_id as i64, category])?;
+    }
+    Ok(())
+}
+
+fn load_categories(conn: &Connection, channel_id: u64) -> Result<BTreeSet<String>, StoreError> {
+    let mut stmt = conn.prepare("SELECT category FROM channel_categories WHERE channel_id = ?1")?;
+    let categories = stmt
+        .query_map(params![channel_id as i64], |row| row.get::<_, String>(0))?
+        .collect::<Result<BTreeSet<String>, _>>()?;
+    Ok(categories)
+}
+
+fn replace_filters(conn: &Connection, channel_id: u64, filters: &[FilterRule]) -> Result<(), StoreError> {
+    conn.execute("DELETE FROM channel_filters WHERE channel_id = ?1", params![channel_id as i64])?;
+    let mut insert = conn.prepare("INSERT INTO channel_filters (channel_id, position, mode, pattern, is_regex) VALUES (?1, ?2, ?3, ?4, ?5)")?;
+    for (position, filter) in filters.iter().enumerate() {
+        insert.execute(params![channel_id as i64, position as i64, filter.mode.name(), filter.pattern, filter.is_regex])?;
+    }
+    Ok(())
+}
+
+fn load_filters(conn: &Connection, channel_id: u64) -> Result<Vec<FilterRule>, StoreError> {
+    let mut stmt = conn.prepare("SELECT mode, pattern, is_regex FROM channel_filters WHERE channel_id = ?1 ORDER BY position")?;
+    let rows = stmt
+        .query_map(params![channel_id as i64], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, bool>(2)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    // Rows with an unknown mode can only come from a newer version, skip rather than misapply them
+    Ok(rows.into_iter()
+        .filter_map(|(mode, pattern, is_regex)| FilterMode::from_name(&mode).map(|mode| FilterRule { mode, pattern, is_regex }))
+        .collect())
+}
+
+// Categories and platforms are stored comma-separated, neither can contain a comma
+fn replace_pings(conn: &Connection, channel_id: u64, pings: &[PingRule]) -> Result<(), StoreError> {
+    conn.execute("DELETE FROM channel_pings WHERE channel_id = ?1", params![channel_id as i64])?;
+    let mut insert = conn.prepare("INSERT INTO channel_pings (channel_id, target, categories, platforms) VALUES (?1, ?2, ?3, ?4)")?;
+    for ping in pings {
+        let categories = ping.categories.iter().cloned().collect::<Vec<_>>().join(",");
+        let platforms = ping.platforms.iter().cloned().collect::<Vec<_>>().join(",");
+        insert.execute(params![channel_id as i64, ping.target.key(), categories, platforms])?;
+    }
+    Ok(())
+}
+
+fn load_pings(conn: &Connection, channel_id: u64) -> Result<Vec<PingRule>, StoreError> {
+    let mut stmt = conn.prepare("SELECT target, categories, platforms FROM channel_pings WHERE channel_id = ?1 ORDER BY target")?;
+    let rows = stmt
+        .query_map(params![channel_id as i64], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let split = |list: &str| list.split(',').filter(|s| !s.is_empty()).map(str::to_string).collect::<BTreeSet<String>>();
+    Ok(rows.into_iter()
+        .filter_map(|(target, categories, platforms)| PingTarget::from_key(&target).map(|target| PingRule {
+            target,
+            categories: split(&categories),
+            platforms: split(&platforms),
+        }))
+        .collect())
+}
+
+fn write_setting(conn: &Connection, channel_id: u64, key: &str, value: Option<&str>) -> Result<(), StoreError> {
+    match value {
+        Some(value) => conn.execute(
+            "INSERT INTO channel_settings (channel_id, key, value) VALUES (?1, ?2, ?3)
+             ON CONFLICT(channel_id, key) DO UPDATE SET value = excluded.value",
+            params![channel_id as i64, key, value],
+        )?,
+        None => conn.execute("DELETE FROM channel_settings WHERE channel_id = ?1 AND key = ?2", params![channel_id as i64, key])?,
+    };
+    Ok(())
+}
+
+fn load_settings(conn: &Connection, channel_id: u64) -> Result<BTreeMap<String, String>, StoreError> {
+    let mut stmt = conn.prepare("SELECT key, value FROM channel_settings WHERE channel_id = ?1")?;
+    let settings = stmt
+        .query_map(params![channel_id as i64], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<BTreeMap<String, String>, _>>()?;
+    Ok(settings)
+}
+
+fn channel_exists(conn: &Connection, channel_id: u64) -> Result<bool, StoreError> {
+    let exists = conn
+        .query_row("SELECT 1 FROM channels WHERE channel_id = ?1", params![channel_id as i64], |_| Ok(()))
+        .optional()?
+        .is_some();
+    Ok(exists)
+}
+
+fn load_platforms(conn: &Connection, channel_id: u64) -> Result<BTreeSet<String>, StoreError> {
+    let mut stmt = conn.prepare("SELECT platform FROM channel_platforms WHERE channel_id = ?1")?;
+    let platforms = stmt
+        .query_map(params![channel_id as i64], |row| row.get::<_, String>(0))?
+        .collect::<Result<BTreeSet<String>, _>>()?;
+    Ok(platforms)
+}
+
+/// Columns of `news_archive` read by [`archive_row`], in order.
+const ARCHIVE_COLUMNS: &str = "news_archive.id, news_archive.title, news_archive.summary, news_archive.platforms, \
+    news_archive.updated, news_archive.thumbnail, news_archive.tags";
+
+fn archive_row(row: &Row) -> rusqlite::Result<NewsItem> {
+    let split = |text: String| text.split(',').filter(|s| !s.is_empty()).map(str::to_string).collect::<Vec<_>>();
+    Ok(NewsItem::from_archive(
+        row.get::<_, i64>(0)? as u64,
+        row.get(1)?,
+        row.get(2)?,
+        split(row.get(3)?).into_iter().collect(),
+        row.get(4)?,
+        row.get(5)?,
+        split(row.get(6)?),
+    ))
+}
+
+fn archive_timestamp(timestamp: chrono::DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// An FTS5 query requiring every term as a word prefix, or `None` if no term has anything to search for.
+fn fts_query(terms: &[String]) -> Option<String> {
+    let phrases: Vec<String> = terms.iter()
+        .filter(|term| term.chars().any(char::is_alphanumeric))
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect();
+    if phrases.is_empty() { None } else { Some(phrases.join(" ")) }
+}
+
+impl SubscriptionStore for SqliteStore {
+    fn list_subscriptions(&self) -> Result<Vec<Subscription>, StoreError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT channel_id, guild_id FROM channels ORDER BY channel_id")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, Option<i64>>(1)?.map(|id| id as u64))))?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(channel_id, guild_id)| Ok(Subscription {
+                channel_id,
+                guild_id,
+                platforms: load_platforms(&conn, channel_id)?,
+                categories: load_categories(&conn, channel_id)?,
+                filters: load_filters(&conn, channel_id)?,
+                pings: load_pings(&conn, channel_id)?,
+                settings: load_settings(&conn, channel_id)?,
+            }))
+            .collect()
+    }
+
+    fn get_subscription(&self, channel_id: u64) -> Result<Option<Subscription>, StoreError> {
+        let conn = self.conn.lock().unwrap();
+        let guild_id: Option<Option<i64>> = conn
+            .query_row("SELECT guild_id FROM channels WHERE channel_id = ?1", params![channel_id as i64], |row| row.get(0))
+            .optional()?;
+        match guild_id {
+            Some(guild_id) => Ok(Some(Subscription {
+                channel_id,
+                guild_id: guild_id.map(|id| id as u64),
+                platforms: load_platforms(&conn, channel_id)?,
+                categories: load_categories(&conn, channel_id)?,
+                filters: load_filters(&conn, channel_id)?,
+                pings: load_pings(&conn, channel_id)?,
+                settings: load_settings(&conn, channel_id)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    fn insert_subscription(&self, subscription: &Subscription) -> Result<bool, StoreError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let updated = tx.execute(
+            "UPDATE channels SET guild_id = COALESCE(guild_id, ?2) WHERE channel_id = ?1",
+            params![subscription.channel_id as i64, subscription.guild_id.map(|id| id as i64)],
+        )?;
+        if updated == 0 {
+            upsert(&tx, subscription)?;
+        }
+        tx.commit()?;
+        Ok(updated == 0)
+    }
+
+    fn remove_subscription(&self, channel_id: u64) -> Result<bool, StoreError> {
+        let conn = self.conn.lock().unwrap();
+        let removed = conn.execute("DELETE FROM channels WHERE channel_id = ?1", params![channel_id as i64])?;
+        Ok(removed > 0)
+    }
+
+    fn set_platforms(&self, channel_id: u64, platforms: &BTreeSet<String>) -> Result<bool, StoreError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        if !channel_exists(&tx, channel_id)? {
+            return Ok(false);
+        }
+        replace_platforms(&tx, channel_id, platforms)?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    fn set_categories(&self, channel_id: u64, categories: &BTreeSet<String>) -> Result<bool, StoreError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        if !channel_exists(&tx, channel_id)? {
+            return Ok(false);
+        }
+        replace_categories(&tx, channel_id, categories)?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    fn set_filters(&self, channel_id: u64, filters: &[FilterRule]) -> Result<bool, StoreError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        if !channel_exists(&tx, channel_id)? {
+            return Ok(false);
+        }
+        replace_filters(&tx, channel_id, filters)?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    fn set_pings(&self, channel_id: u64, pings: &[PingRule]) -> Result<bool, StoreError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        if !channel_exists(&tx, channel_id)? {
+            return Ok(false);
+        }
+        replace_pings(&tx, channel_id, pings)?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    fn set_setting(&self, channel_id: u64, key: &str, value: Option<&str>) -> Result<bool, StoreError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        if !channel_exists(&tx, channel_id)? {
+            return Ok(false);
+        }
+        write_setting(&tx, channel_id, key, value)?;
+        tx.commit()?;
+        Ok(true)
+    }
+}
+
+impl DeliveryLedger for SqliteStore {
+    fn is_delivered(&self, channel_id: u64, news_id: u64) -> Result<bool, StoreError> {
+        let conn = self.conn.lock().unwrap();
+        let delivered = conn
+            .query_row(
+                "SELECT 1 FROM deliveries WHERE channel_id = ?1 AND news_id = ?2",
+                params![channel_id as i64, news_id as i64],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        Ok(delivered)
+    }
+
+    fn is_bootstrapped(&self, channel_id: u64) -> Result<bool, StoreError> {
+        let conn = self.conn.lock().unwrap();
+        let bootstrapped = conn
+            .query_row("SELECT 1 FROM ledger_bootstraps WHERE channel_id = ?1", params![channel_id as i64], |_| Ok(()))
+            .optional()?
+            .is_some();
+        Ok(bootstrapped)
+    }
+
+    fn mark_bootstrapped(&self, channel_id: u64) -> Result<(), StoreError> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR IGNORE INTO ledger_bootstraps (channel_id, bootstrapped_at) VALUES (?1, ?2)",
+            params![channel_id as i64, Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    fn record_delivery(&self, channel_id: u64, news_id: u64, message_id: u64) -> Result<(), StoreError> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO deliveries (channel_id, news_id, message_id, delivered_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(channel_id, news_id) DO UPDATE SET message_id = excluded.message_id, delivered_at = excluded.delivered_at",
+            params![channel_id as i64, news_id as i64, message_id as i64, Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    fn deliveries_of(&self, news_id: u64) -> Result<Vec<(u64, u64)>, StoreError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT channel_id, message_id FROM deliveries WHERE news_id = ?1 ORDER BY channel_id")?;
+        let deliveries = stmt
+            .query_map(params![news_id as i64], |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(deliveries)
+    }
+
+    fn watermark(&self, channel_id: u64) -> Result<Option<Watermark>, StoreError> {
+        let conn = self.conn.lock().unwrap();
+        let row: Option<(Option<i64>, String)> = conn
+            .query_row("SELECT news_id, updated_at FROM channel_watermarks WHERE channel_id = ?1", params![channel_id as i64], |row| Ok((row.get(0)?, row.get(1)?)))
+            .optional()?;
+        // An unreadable mark is treated like a missing one, which the poller starts afresh
+        Ok(row.and_then(|(news_id, updated)| {
+            let updated_at = DateTime::parse_from_rfc3339(&updated).ok()?.with_timezone(&Utc);
+            Some(Watermark { updated_at, news_id: news_id.map(|id| id as u64) })
+        }))
+    }
+
+    fn set_watermark(&self, channel_id: u64, news_id: Option<u64>, updated: DateTime<Utc>) -> Result<(), StoreError> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO channel_watermarks (channel_id, news_id, updated_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(channel_id) DO UPDATE SET news_id = excluded.news_id, updated_at = excluded.updated_at",
+            params![channel_id as i64, news_id.map(|id| id as i64), archive_timestamp(updated)],
+        )?;
+        Ok(())
+    }
+}
+
+impl NewsArchive for SqliteStore {
+    fn archive_news(&self, items: &[NewsItem]) -> Result<usize, StoreError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut added = 0;
+        for item in items {
+            let existed = tx
+                .query_row("SELECT 1 FROM news_archive WHERE id = ?1", params![item.get_id() as i64], |_| Ok(()))
+                .optional()?
+                .is_some();
+            tx.execute(
+                "INSERT INTO news_archive (id, title, summary, platforms, updated, updated_at, thumbnail, tags, archived_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                 ON CONFLICT(id) DO UPDATE SET title = excluded.title, summary = excluded.summary, platforms = excluded.platforms,
+                     updated = excluded.updated, updated_at = excluded.updated_at, thumbnail = excluded.thumbnail, tags = excluded.tags",
+                params![
+                    item.get_id() as i64,
+                    item.get_title(),
+                    item.get_summary(),
+                    item.get_platforms().iter().cloned().collect::<Vec<_>>().join(","),
+                    item.get_updated(),
+                    item.updated_at().map(archive_timestamp),
+                    item.get_thumbnail_url(),
+                    item.get_tags().join(","),
+                    Utc::now().to_rfc3339(),
+                ],
+            )?;
+            if !existed {
+                added += 1;
+            }
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+
+    fn archived_count(&self) -> Result<usize, StoreError> {
+        let conn = self.conn.lock().unwrap();
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM news_archive", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    fn archived_item(&self, news_id: u64) -> Result<Option<NewsItem>, StoreError> {
+        let conn = self.conn.lock().unwrap();
+        let item = conn
+            .query_row(&format!("SELECT {} FROM news_archive WHERE id = ?1", ARCHIVE_COLUMNS), params![news_id as i64], archive_row)
+            .optional()?;
+        Ok(item)
+    }
+
+    fn search_archive(&self, query: &SearchQuery, limit: usize) -> Result<Vec<NewsItem>, StoreError> {
+        let Some(fts) = fts_query(&query.terms) else {
+            return Ok(Vec::new());
+        };
+        let mut sql = format!(
+            "SELECT {} FROM news_fts JOIN news_archive ON news_archive.id = news_fts.rowid WHERE news_fts MATCH ?1",
+            ARCHIVE_COLUMNS,
+        );
+        let mut values = vec![fts];
+        if let Some(since) = query.since {
+            values.push(archive_timestamp(since));
+            sql.push_str(&format!(" AND news_archive.updated_at >= ?{}", values.len()));
+        }
+        if let Some(until) = query.until {
+            values.push(archive_timestamp(until));
+            sql.push_str(&format!(" AND news_archive.updated_at <= ?{}", values.len()));
+        }
+        sql.push_str(&format!(" ORDER BY news_fts.rank LIMIT {}", limit));
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&sql)?;
+        let items = stmt
+            .query_map(params_from_iter(values.iter()), archive_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(items)
+    }
+
+    fn backfill_state(&self) -> Result<BackfillState, StoreError> {
+        let conn = self.conn.lock().unwrap();
+        let read = |key: &str| conn.query_row("SELECT value FROM meta WHERE key = ?1", params![key], |row| row.get::<_, String>(0)).optional();
+        if read(BACKFILL_COMPLETE_KEY)?.is_some() {
+            return Ok(BackfillState::Complete);
+        }
+        // An unreadable offset restarts from the newest page, which is slower but loses nothing
+        let offset = read(BACKFILL_OFFSET_KEY)?.and_then(|offset| offset.parse().ok()).unwrap_or(0);
+        Ok(BackfillState::Partial(offset))
+    }
+
+    fn set_backfill_state(&self, state: BackfillState) -> Result<(), StoreError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let (key, value, other) = match state {
+            BackfillState::Partial(offset) => (BACKFILL_OFFSET_KEY, offset.to_string(), BACKFILL_COMPLETE_KEY),
+            BackfillState::Complete => (BACKFILL_COMPLETE_KEY, Utc::now().to_rfc3339(), BACKFILL_OFFSET_KEY),
+        };
+        tx.execute("DELETE FROM meta WHERE key = ?1", params![other])?;
+        tx.execute("INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)", params![key, value])?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::news::News;
+    use crate::test_support::{memory_store, news_fixture};
+
+    fn subscription(channel_id: u64) -> Subscription {
+        Subscription {
+            channel_id,
+            guild_id: Some(7),
+            platforms: BTreeSet::from(["pc".to_string()]),
+            categories: BTreeSet::new(),
+            filters: Vec::new(),
+            pings: Vec::new(),
+            settings: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn migrates_from_every_version() {
+        let store = SqliteStore::from_connection(Connection::open_in_memory().unwrap()).unwrap();
+        let version: usize = store.conn.lock().unwrap().pragma_query_value(None, "user_version", |row| row.get(0)).unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+        assert!(store.list_subscriptions().unwrap().is_empty());
+
+        // A database from the first release keeps its channels, which start with a watermark
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        conn.pragma_update(None, "user_version", 1).unwrap();
+        conn.execute("INSERT INTO channels (channel_id, guild_id, created_at) VALUES (1, NULL, '2024-01-01T00:00:00Z')", []).unwrap();
+        conn.execute("INSERT INTO channel_platforms (channel_id, platform) VALUES (1, 'pc')", []).unwrap();
+        let store = SqliteStore::from_connection(conn).unwrap();
+        let migrated = store.get_subscription(1).unwrap().unwrap();
+        assert_eq!(migrated.platforms, BTreeSet::from(["pc".to_string()]));
+        assert!(migrated.categories.is_empty());
+        assert!(store.watermark(1).unwrap().is_some());
+
+        // Migrating an up-to-date database changes nothing
+        let mut conn = store.conn.into_inner().unwrap();
+        SqliteStore::migrate(&mut conn).unwrap();
+        let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn imports_legacy_channels_once() {
+        let path = std::env::temp_dir().join(format!("stobot-channels-{}.txt", std::process::id()));
+        std::fs::write(&path, "channel:1|pc,Playstation\nchannel:2|\nnot a channel\nchannel:x|pc\nchannel:3|nintendo\n").unwrap();
+        let path = path.to_string_lossy().to_string();
+        let defaults = BTreeSet::from(["pc".to_string(), "xbox".to_string()]);
+        let store = memory_store();
+
+        let imported = store.import_legacy_channels(&path, &defaults);
+        let again = store.import_legacy_channels(&path, &defaults);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(imported.unwrap(), Some(3));
+        assert_eq!(again.unwrap(), None);
+        let platforms = |id| store.get_subscription(id).unwrap().unwrap().platforms;
+        assert_eq!(platforms(1), BTreeSet::from(["pc".to_string(), "ps".to_string()]));
+        assert_eq!(platforms(2), defaults);
+        assert_eq!(platforms(3), defaults);
+        assert_eq!(store.list_subscriptions().unwrap().len(), 3);
+        assert_eq!(memory_store().import_legacy_channels("/nonexistent/channels.txt", &defaults).unwrap(), None);
+    }
+
+    #[test]
+    fn changes_only_registered_channels() {
+        let store = memory_store();
+        let rule = FilterRule { mode: FilterMode::Exclude, pattern: "lockbox".to_string(), is_regex: false };
+        let ping = PingRule { target: PingTarget::Here, categories: BTreeSet::new(), platforms: BTreeSet::new() };
+        let categories = BTreeSet::from(["events".to_string()]);
+        let platforms = BTreeSet::from(["xbox".to_string()]);
+        assert!(!store.set_platforms(1, &platforms).unwrap());
+        assert!(!store.set_categories(1, &categories).unwrap());
+        assert!(!store.set_filters(1, std::slice::from_ref(&rule)).unwrap());
+        assert!(!store.set_pings(1, std::slice::from_ref(&ping)).unwrap());
+        assert!(!store.set_setting(1, "post_mode", Some("thread")).unwrap());
+        assert!(store.get_subscription(1).unwrap().is_none());
+        assert!(!store.remove_subscription(1).unwrap());
+
+        assert!(store.insert_subscription(&subscription(1)).unwrap());
+        assert!(store.set_platforms(1, &platforms).unwrap());
+        assert!(store.set_categories(1, &categories).unwrap());
+        assert!(store.set_filters(1, std::slice::from_ref(&rule)).unwrap());
+        assert!(store.set_pings(1, std::slice::from_ref(&ping)).unwrap());
+        assert!(store.set_setting(1, "post_mode", Some("thread")).unwrap());
+        let changed = store.get_subscription(1).unwrap().unwrap();
+        assert_eq!(changed.platforms, platforms);
+        assert_eq!(changed.categories, categories);
+        assert_eq!(changed.filters, vec![rule]);
+        assert_eq!(changed.pings, vec![ping]);
+        assert_eq!(changed.settings.get("post_mode").map(String::as_str), Some("thread"));
+        assert!(store.set_setting(1, "post_mode", None).unwrap());
+        assert!(store.get_subscription(1).unwrap().unwrap().settings.is_empty());
+
+        // Registering again keeps the whole configuration, and the guild when it isn't known
+        assert!(store.set_setting(1, "post_mode", Some("thread")).unwrap());
+        assert!(!store.insert_subscription(&Subscription { guild_id: None, ..subscription(1) }).unwrap());
+        assert_eq!(store.get_subscription(1).unwrap().unwrap(), changed);
+        assert!(store.insert_subscription(&subscription(2)).unwrap());
+        assert_eq!(store.get_subscription(2).unwrap().unwrap(), subscription(2));
+        assert!(store.remove_subscription(1).unwrap());
+        assert!(!store.set_platforms(1, &platforms).unwrap());
+    }
+
+    #[test]
+    fn remembers_bootstrapped_channels() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(&MIGRATIONS[..2].join("\n")).unwrap();
+        conn.pragma_update(None, "user_version", 2).unwrap();
+        conn.execute("INSERT INTO deliveries (channel_id, news_id, message_id, delivered_at) VALUES (1, 10, 100, '2024-01-01T00:00:00Z')", []).unwrap();
+        let store = SqliteStore::from_connection(conn).unwrap();
+        // Channels that already had deliveries count as scanned
+        assert!(store.is_bootstrapped(1).unwrap());
+        assert!(!store.is_bootstrapped(2).unwrap());
+        store.mark_bootstrapped(2).unwrap();
+        store.mark_bootstrapped(2).unwrap();
+        assert!(store.is_bootstrapped(2).unwrap());
+    }
+
+    #[test]
+    fn archives_and_searches_news() {
+        let store = memory_store();
+        let news: News = serde_json::from_str(&news_fixture()).unwrap();
+        assert_eq!(store.archive_news(news.as_slice()).unwrap(), 3);
+        // Archiving again only refreshes the items
+        assert_eq!(store.archive_news(news.as_slice()).unwrap(), 0);
+        assert_eq!(store.archived_count().unwrap(), 3);
+
+        let ids = |query: SearchQuery| store.search_archive(&query, 10).unwrap().iter().map(|item| item.get_id()).collect::<Vec<_>>();
+        assert_eq!(ids(SearchQuery::parse("PATCH", None, None, None, None).unwrap()), vec![11100002]);
+        assert!(ids(SearchQuery::parse("sale", Some("2022-01-01"), None, None, None).unwrap()).is_empty());
+        assert!(ids(SearchQuery::parse("\"-\"", None, None, None, None).unwrap()).is_empty());
+
+        // Archived items come back whole, so they render and rank like fetched ones
+        let sale = &store.search_archive(&SearchQuery::parse("sale", None, None, None, None).unwrap(), 10).unwrap()[0];
+        let original = news.iter().find(|item| item.get_id() == sale.get_id()).unwrap();
+        assert_eq!(sale.get_thumbnail_url(), original.get_thumbnail_url());
+        assert_eq!(sale.categories(), original.categories());
+        assert_eq!(sale.updated_at(), original.updated_at());
+    }
+}
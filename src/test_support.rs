@@ -0,0 +1,138 @@
+// Local stand-ins for the ARC Games API and STOWiki, serving the fixtures in tests/fixtures
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::Utc;
+use chrono_tz::America::Los_Angeles;
+use serenity::http::HttpBuilder;
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use crate::arc_api::{ArcClient, PAGE_SIZE};
+use crate::poller::{PollMetrics, Poller, PollerConfig, DEFAULT_MAX_CATCH_UP, DEFAULT_POLL_COUNT};
+use crate::store::{SqliteStore, Subscription, SubscriptionStore};
+
+pub const NEWS_PATH: &str = "/v1.0/games/sto/news";
+
+/// The news fixture, with every `{{now}}` replaced by the current time in the API's timezone
+pub fn news_fixture() -> String {
+    let now = Utc::now().with_timezone(&Los_Angeles).format("%Y-%m-%d %H:%M:%S").to_string();
+    include_str!("../tests/fixtures/news.json").replace("{{now}}", &now)
+}
+
+/// The single article fixture, for the news item with the given ID
+pub fn article_fixture() -> String {
+    let now = Utc::now().with_timezone(&Los_Angeles).format("%Y-%m-%d %H:%M:%S").to_string();
+    include_str!("../tests/fixtures/article.json").replace("{{now}}", &now)
+}
+
+/// A MediaWiki full-text search answer for "Defiant"
+pub fn wiki_search_fixture() -> String {
+    include_str!("../tests/fixtures/wiki_search.json").to_string()
+}
+
+/// A MediaWiki parse answer for the "Defiant" redirect to "Defiant Class"
+pub fn wiki_parse_fixture() -> String {
+    include_str!("../tests/fixtures/wiki_parse.json").to_string()
+}
+
+/// `count` news items shaped like the first one of the news fixture, newest first, numbered down from `count`.
+pub fn news_history(count: u64) -> Vec<serde_json::Value> {
+    let fixture: serde_json::Value = serde_json::from_str(&news_fixture()).unwrap();
+    (1..=count).rev()
+        .map(|id| {
+            let mut item = fixture["news"][0].clone();
+            item["id"] = id.into();
+            item["title"] = format!("News {}", id).into();
+            item
+        })
+        .collect()
+}
+
+pub fn memory_store() -> SqliteStore {
+    SqliteStore::open(":memory:").expect("in-memory database")
+}
+
+/// A poller with the default settings and channel 42 subscribed to PC news, talking to `upstream` for
+/// the ARC API and Discord alike.
+pub fn test_poller(upstream: &MockUpstream) -> Poller {
+    let store = memory_store();
+    let platforms = BTreeSet::from(["pc".to_string()]);
+    store.insert_subscription(&Subscription {
+        channel_id: 42,
+        guild_id: None,
+        platforms: platforms.clone(),
+        categories: BTreeSet::new(),
+        filters: Vec::new(),
+        pings: Vec::new(),
+        settings: Default::default(),
+    }).unwrap();
+    Poller::new(
+        PollerConfig { poll_period: 600, poll_count: DEFAULT_POLL_COUNT, max_catch_up: DEFAULT_MAX_CATCH_UP, msg_count: 0, default_platforms: platforms },
+        Arc::new(store),
+        ArcClient::new(&upstream.api_url(), Duration::from_secs(5), 0).unwrap(),
+        Arc::new(HttpBuilder::new("token").proxy(upstream.server().uri()).ratelimiter_disabled(true).build()),
+        Arc::new(PollMetrics::default()),
+    )
+}
+
+pub struct MockUpstream {
+    server: MockServer,
+}
+
+impl MockUpstream {
+    pub async fn start() -> MockUpstream {
+        MockUpstream { server: MockServer::start().await }
+    }
+
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    pub fn api_url(&self) -> String {
+        format!("{}{}", self.server.uri(), NEWS_PATH)
+    }
+
+    pub fn wiki_url(&self) -> String {
+        self.server.uri()
+    }
+
+    pub async fn serve_news(&self, body: String) {
+        Mock::given(method("GET"))
+            .and(path(NEWS_PATH))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Serve `items` a page per offset, like the API pages through the history.
+    pub async fn serve_news_pages(&self, items: &[serde_json::Value]) {
+        for (page, chunk) in items.chunks(PAGE_SIZE as usize).enumerate() {
+            Mock::given(method("GET"))
+                .and(path(NEWS_PATH))
+                .and(query_param("offset", (page * PAGE_SIZE as usize).to_string()))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "news": chunk })))
+                .mount(&self.server)
+                .await;
+        }
+    }
+
+    pub async fn serve_article(&self, id: u64, body: String) {
+        Mock::given(method("GET"))
+            .and(path(format!("{}/{}", NEWS_PATH, id)))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Answer MediaWiki API requests carrying all of `params`.
+    pub async fn serve_wiki_api(&self, params: &[(&str, &str)], body: String) {
+        let mut mock = Mock::given(method("GET")).and(path("/api.php"));
+        for (key, value) in params {
+            mock = mock.and(query_param(*key, *value));
+        }
+        mock.respond_with(ResponseTemplate::new(200).set_body_string(body).insert_header("content-type", "application/json"))
+            .mount(&self.server)
+            .await;
+    }
+}
@@ -0,0 +1,702 @@
+// STOWiki lookups through the MediaWiki API
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{StatusCode, Url};
+use scraper::{ElementRef, Html, Node, Selector};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serenity::builder::{CreateEmbed, CreateEmbedFooter};
+
+use crate::infobox::Infobox;
+use crate::markdown::{escape, truncate, EMBED_FIELD_VALUE_LIMIT, EMBED_TITLE_LIMIT};
+use crate::wiki_cache::{CachedResponse, WikiCache};
+
+pub const DEFAULT_WIKI_URL: &str = "https://stowiki.net";
+
+/// Path of the MediaWiki API below the wiki's base URL.
+const API_PATH: &str = "api.php";
+
+/// Search matches listed below the preview.
+pub const MAX_WIKI_MATCHES: usize = 5;
+
+/// Longest preview paragraph shown, the rest is a click away.
+const PREVIEW_LIMIT: usize = 1000;
+
+/// Longest snippet shown per search match.
+const SNIPPET_LIMIT: usize = 120;
+
+/// Longest query repeated in the answer when nothing was found.
+const QUERY_ECHO_LIMIT: usize = 200;
+
+/// How long title completions are reused, short so new pages show up soon.
+const COMPLETION_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Most prefixes kept in the completion cache.
+const COMPLETION_CACHE_SIZE: usize = 500;
+
+/// Discord gives up on an autocomplete answer after 3 seconds, leave time to respond.
+const COMPLETION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Discord accepts at most 25 autocomplete choices of at most 100 characters.
+const MAX_COMPLETIONS: usize = 25;
+const COMPLETION_LENGTH_LIMIT: usize = 100;
+
+/// Page property set by the PageImages extension to the page's representative free image.
+const PAGE_IMAGE_PROPERTY: &str = "page_image_free";
+
+/// API error codes for pages that don't exist, which are answers worth caching.
+const MISSING_PAGE_CODES: &[&str] = &["missingtitle", "invalidtitle"];
+
+/// STO blue, used for every wiki embed.
+const WIKI_COLOR: u32 = 0x00ADEF;
+
+/// Who sees the answer to a wiki lookup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WikiVisibility {
+    /// Only the member who asked
+    #[default]
+    Private,
+    /// Everyone in the channel
+    Shared,
+}
+
+impl WikiVisibility {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WikiVisibility::Private => "private",
+            WikiVisibility::Shared => "shared",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<WikiVisibility> {
+        match name.trim().to_lowercase().as_str() {
+            "private" => Some(WikiVisibility::Private),
+            "shared" => Some(WikiVisibility::Shared),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WikiError {
+    /// The request could not be sent or the response body could not be read
+    Network(reqwest::Error),
+    /// The wiki answered with a non-success status
+    Status(StatusCode),
+    /// The response body was not the expected JSON shape
+    Json(serde_json::Error),
+    /// The API reported an error of its own, other than a missing page
+    Api(String),
+}
+
+impl fmt::Display for WikiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WikiError::Network(e) => write!(f, "network error: {}", e),
+            WikiError::Status(status) => write!(f, "unexpected HTTP status {}", status),
+            WikiError::Json(e) => write!(f, "unexpected response shape: {}", e),
+            WikiError::Api(info) => write!(f, "wiki API error: {}", info),
+        }
+    }
+}
+
+impl std::error::Error for WikiError {}
+
+/// A page found by a full-text search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WikiMatch {
+    pub title: String,
+    pub url: String,
+    /// Text around the match as markdown, with the matched words in bold
+    pub snippet: String,
+}
+
+/// A page picked for the preview.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WikiPage {
+    /// Title after following redirects
+    pub title: String,
+    pub url: String,
+    /// The title that was asked for, if it redirected here
+    pub redirected_from: Option<String>,
+    /// First paragraph of the page
+    pub summary: Option<String>,
+    /// Stats of ships, traits, consoles and equipment
+    pub infobox: Option<Infobox>,
+    /// The infobox image, or else the page image the wiki picked
+    pub image: Option<String>,
+}
+
+/// Everything found for one query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WikiResult {
+    pub query: String,
+    /// The page best matching the query, if any
+    pub page: Option<WikiPage>,
+    pub matches: Vec<WikiMatch>,
+    /// The wiki's own search page for the query
+    pub search_url: String,
+}
+
+impl WikiResult {
+    pub fn to_embed(&self) -> CreateEmbed {
+        let mut embed = CreateEmbed::new()
+            .color(WIKI_COLOR)
+            .footer(CreateEmbedFooter::new("Results from STOWiki.net, the community Star Trek Online wiki"));
+        let more = format!("[All search results]({})", self.search_url);
+        match &self.page {
+            Some(page) => {
+                let mut description = String::new();
+                if let Some(from) = &page.redirected_from {
+                    description.push_str(&format!("*Redirected from {}*\n\n", escape(from)));
+                }
+                if let Some(summary) = &page.summary {
+                    description.push_str(&truncate(summary, PREVIEW_LIMIT));
+                    description.push_str("\n\n");
+                }
+                description.push_str(&more);
+                embed = embed.title(&page.title).url(&page.url).description(description);
+                if let Some(infobox) = &page.infobox {
+                    embed = infobox.apply(embed);
+                }
+                if let Some(image) = &page.image {
+                    embed = embed.thumbnail(image);
+                }
+            },
+            None => {
+                // Escaped rather than in a code span, which a backtick in the query would break out of
+                let query = escape(&truncate(&self.query, QUERY_ECHO_LIMIT));
+                embed = embed
+                    .title(truncate(&format!("STOWiki Search: {}", self.query), EMBED_TITLE_LIMIT))
+                    .description(format!("No pages found for \"{}\".\n\n{}", query, more));
+            },
+        }
+        let shown = self.page.as_ref().map(|p| p.title.as_str());
+        let others: Vec<String> = self.matches.iter()
+            .filter(|m| Some(m.title.as_str()) != shown)
+            .map(|m| if m.snippet.is_empty() {
+                format!("[{}]({})", escape(&m.title), m.url)
+            } else {
+                format!("[{}]({}): {}", escape(&m.title), m.url, m.snippet)
+            })
+            .collect();
+        if !others.is_empty() {
+            let mut value = String::new();
+            for line in others {
+                if value.chars().count() + line.chars().count() + 1 > EMBED_FIELD_VALUE_LIMIT {
+                    break;
+                }
+                value.push_str(&line);
+                value.push('\n');
+            }
+            embed = embed.field("Other matches", value, false);
+        }
+        embed
+    }
+}
+
+#[derive(Deserialize)]
+struct ApiErrorBody {
+    code: String,
+    info: String,
+}
+
+/// Just the error of any API response.
+#[derive(Deserialize)]
+struct ErrorProbe {
+    error: Option<ApiErrorBody>,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    query: Option<SearchQueryResult>,
+    error: Option<ApiErrorBody>,
+}
+
+#[derive(Deserialize)]
+struct SearchQueryResult {
+    #[serde(default)]
+    search: Vec<SearchHit>,
+    searchinfo: Option<SearchInfo>,
+}
+
+#[derive(Deserialize)]
+struct SearchInfo {
+    suggestion: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SearchHit {
+    title: String,
+    #[serde(default)]
+    snippet: String,
+}
+
+#[derive(Deserialize)]
+struct PrefixSearchResponse {
+    query: Option<PrefixSearchResult>,
+    error: Option<ApiErrorBody>,
+}
+
+#[derive(Deserialize)]
+struct PrefixSearchResult {
+    #[serde(default)]
+    prefixsearch: Vec<PrefixHit>,
+}
+
+#[derive(Deserialize)]
+struct PrefixHit {
+    title: String,
+}
+
+#[derive(Deserialize)]
+struct ParseResponse {
+    parse: Option<Parsed>,
+    error: Option<ApiErrorBody>,
+}
+
+#[derive(Deserialize)]
+struct Parsed {
+    title: String,
+    #[serde(default)]
+    redirects: Vec<Redirect>,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    properties: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct Redirect {
+    from: String,
+}
+
+/// Page titles by lowercased prefix, with the time they were fetched.
+type CompletionCache = HashMap<String, (Instant, Vec<String>)>;
+
+/// Client for the MediaWiki API of STOWiki.
+#[derive(Clone)]
+pub struct WikiClient {
+    http: reqwest::Client,
+    base_url: String,
+    completions: Arc<Mutex<CompletionCache>>,
+    cache: Arc<WikiCache>,
+}
+
+impl WikiClient {
+    pub fn new(base_url: &str, timeout: Duration, cache: WikiCache) -> Result<WikiClient, WikiError> {
+        let http = reqwest::Client::builder()
+            .timeout(timeout)
+            .connect_timeout(timeout)
+            .user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(WikiError::Network)?;
+        Ok(WikiClient {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            completions: Arc::default(),
+            cache: Arc::new(cache),
+        })
+    }
+
+    /// Link to the page with the given title.
+    pub fn page_url(&self, title: &str) -> String {
+        self.wiki_url(&[&title.replace(' ', "_")])
+    }
+
+    /// Link to the file named `file`, which redirects to the image itself.
+    pub fn file_url(&self, file: &str) -> String {
+        self.wiki_url(&["Special:FilePath", &file.replace(' ', "_")])
+    }
+
+    /// Link below `/wiki/` with each of `segments` encoded.
+    fn wiki_url(&self, segments: &[&str]) -> String {
+        match Url::parse(&self.base_url) {
+            Ok(mut url) => {
+                if let Ok(mut path) = url.path_segments_mut() {
+                    path.pop_if_empty().push("wiki").extend(segments);
+                }
+                url.to_string()
+            },
+            Err(_) => format!("{}/wiki/{}", self.base_url, segments.join("/")),
+        }
+    }
+
+    /// Link to the wiki's own search page for `query`.
+    pub fn search_url(&self, query: &str) -> String {
+        Url::parse_with_params(&format!("{}/index.php", self.base_url), &[("title", "Special:Search"), ("search", query), ("go", "Go")])
+            .map_or_else(|_| format!("{}/wiki/Special:Search", self.base_url), |url| url.to_string())
+    }
+
+    /// Find the page best matching `query` and the top full-text matches.
+    ///
+    /// An exact title, or one redirecting to a page, wins over the search ranking. When nothing matches and the
+    /// wiki suggests a spelling, that is searched instead.
+    pub async fn lookup(&self, query: &str) -> Result<WikiResult, WikiError> {
+        let matches = self.search(query, MAX_WIKI_MATCHES).await?;
+        let page = match self.page(query).await? {
+            Some(page) => Some(page),
+            None => match matches.first() {
+                Some(best) => self.page(&best.title).await?,
+                None => None,
+            },
+        };
+        Ok(WikiResult {
+            query: query.to_string(),
+            page,
+            matches,
+            search_url: self.search_url(query),
+        })
+    }
+
+    /// Full-text search, falling back to the wiki's spelling suggestion if there are no matches.
+    pub async fn search(&self, query: &str, limit: usize) -> Result<Vec<WikiMatch>, WikiError> {
+        let (matches, suggestion) = self.search_once(query, limit).await?;
+        match suggestion {
+            Some(suggestion) if matches.is_empty() && suggestion != query => Ok(self.search_once(&suggestion, limit).await?.0),
+            _ => Ok(matches),
+        }
+    }
+
+    async fn search_once(&self, query: &str, limit: usize) -> Result<(Vec<WikiMatch>, Option<String>), WikiError> {
+        let limit = limit.to_string();
+        let response: SearchResponse = self.api(&[
+            ("action", "query"),
+            ("list", "search"),
+            ("srsearch", query),
+            ("srlimit", &limit),
+            ("srprop", "snippet"),
+        ]).await?;
+        if let Some(error) = response.error {
+            return Err(WikiError::Api(error.info));
+        }
+        let Some(result) = response.query else {
+            return Ok((Vec::new(), None));
+        };
+        let matches = result.search.into_iter()
+            .map(|hit| WikiMatch {
+                url: self.page_url(&hit.title),
+                snippet: truncate(&snippet_to_markdown(&hit.snippet), SNIPPET_LIMIT),
+                title: hit.title,
+            })
+            .collect();
+        Ok((matches, result.searchinfo.and_then(|info| info.suggestion)))
+    }
+
+    /// Titles of pages starting with `prefix`, for autocompleting queries. Recent answers are reused.
+    pub async fn complete(&self, prefix: &str) -> Result<Vec<String>, WikiError> {
+        let key = prefix.trim().to_lowercase();
+        if key.is_empty() {
+            return Ok(Vec::new());
+        }
+        if let Some((fetched, titles)) = self.completions.lock().unwrap().get(&key)
+            && fetched.elapsed() < COMPLETION_TTL {
+            return Ok(titles.clone());
+        }
+        let limit = MAX_COMPLETIONS.to_string();
+        let response: PrefixSearchResponse = self.api_with_timeout(&[
+            ("action", "query"),
+            ("list", "prefixsearch"),
+            ("pssearch", prefix.trim()),
+            ("pslimit", &limit),
+        ], COMPLETION_TIMEOUT).await?;
+        if let Some(error) = response.error {
+            return Err(WikiError::Api(error.info));
+        }
+        let titles: Vec<String> = response.query.map(|q| q.prefixsearch).unwrap_or_default().into_iter()
+            .map(|hit| hit.title)
+            .filter(|title| title.chars().count() <= COMPLETION_LENGTH_LIMIT)
+            .collect();
+        let mut cache = self.completions.lock().unwrap();
+        cache.retain(|_, (fetched, _)| fetched.elapsed() < COMPLETION_TTL);
+        if cache.len() >= COMPLETION_CACHE_SIZE {
+            // Everything left is still fresh, drop the oldest entry to make room
+            if let Some(oldest) = cache.iter().min_by_key(|(_, (fetched, _))| *fetched).map(|(k, _)| k.clone()) {
+                cache.remove(&oldest);
+            }
+        }
+        cache.insert(key, (Instant::now(), titles.clone()));
+        Ok(titles)
+    }
+
+    /// The page titled `title`, following redirects, or `None` if there is no such page.
+    pub async fn page(&self, title: &str) -> Result<Option<WikiPage>, WikiError> {
+        let response: ParseResponse = self.api(&[
+            ("action", "parse"),
+            ("page", title),
+            ("redirects", "1"),
+            ("prop", "text|properties"),
+            ("disableeditsection", "1"),
+            ("disablelimitreport", "1"),
+        ]).await?;
+        match (response.parse, response.error) {
+            (Some(parsed), _) => {
+                let infobox = Infobox::extract(&parsed.text, &self.base_url);
+                let image = infobox.as_ref()
+                    .and_then(|infobox| infobox.image.clone())
+                    .or_else(|| parsed.properties.get(PAGE_IMAGE_PROPERTY).map(|file| self.file_url(file)));
+                Ok(Some(WikiPage {
+                    url: self.page_url(&parsed.title),
+                    redirected_from: parsed.redirects.into_iter().next().map(|r| r.from),
+                    summary: first_paragraph(&parsed.text),
+                    title: parsed.title,
+                    infobox,
+                    image,
+                }))
+            },
+            (None, Some(error)) if MISSING_PAGE_CODES.contains(&error.code.as_str()) => Ok(None),
+            (None, Some(error)) => Err(WikiError::Api(error.info)),
+            (None, None) => Ok(None),
+        }
+    }
+
+    fn api_url(&self, params: &[(&str, &str)]) -> Result<Url, WikiError> {
+        let base = format!("{}/{}", self.base_url, API_PATH);
+        let params = params.iter().chain(&[("format", "json"), ("formatversion", "2")]);
+        Url::parse_with_params(&base, params).map_err(|e| WikiError::Api(format!("invalid API URL {}: {}", base, e)))
+    }
+
+    /// Call the API, answering from the cache while the response is fresh and revalidating it once it isn't.
+    async fn api<T: DeserializeOwned>(&self, params: &[(&str, &str)]) -> Result<T, WikiError> {
+        let url = self.api_url(params)?;
+        let key = url.to_string();
+        let cached = self.cache.get(&key);
+        if let Some(cached) = &cached
+            && cached.is_fresh(self.cache.ttl()) {
+            return serde_json::from_str::<T>(&cached.body).map_err(WikiError::Json);
+        }
+        let mut resp = self.conditional_get(url.clone(), cached.as_ref()).await?;
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            match self.cache.revalidate(&key) {
+                Some(cached) => return serde_json::from_str::<T>(&cached.body).map_err(WikiError::Json),
+                // Dropped from the cache while asking, so there is nothing the answer confirms
+                None => resp = self.conditional_get(url, None).await?,
+            }
+        }
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(WikiError::Status(status));
+        }
+        let header = |name| resp.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+        let (etag, last_modified) = (header(ETAG), header(LAST_MODIFIED));
+        let text = resp.text().await.map_err(WikiError::Network)?;
+        let value = serde_json::from_str::<T>(&text).map_err(WikiError::Json)?;
+        if is_cacheable(&text) {
+            self.cache.insert(&key, CachedResponse::new(text, etag, last_modified));
+        }
+        Ok(value)
+    }
+
+    /// GET `url`, only asking for the body if it changed since `cached` when given.
+    async fn conditional_get(&self, url: Url, cached: Option<&CachedResponse>) -> Result<reqwest::Response, WikiError> {
+        let mut request = self.http.get(url);
+        if let Some(cached) = cached {
+            if let Some(etag) = &cached.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        request.send().await.map_err(WikiError::Network)
+    }
+
+    /// Call the API with a `timeout` shorter than the client's, bypassing the cache.
+    async fn api_with_timeout<T: DeserializeOwned>(&self, params: &[(&str, &str)], timeout: Duration) -> Result<T, WikiError> {
+        let resp = self.http.get(self.api_url(params)?)
+            .timeout(timeout)
+            .send().await.map_err(WikiError::Network)?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(WikiError::Status(status));
+        }
+        let text = resp.text().await.map_err(WikiError::Network)?;
+        serde_json::from_str::<T>(&text).map_err(WikiError::Json)
+    }
+}
+
+/// Whether a response body is an answer rather than a passing API error, like rate limiting.
+fn is_cacheable(body: &str) -> bool {
+    match serde_json::from_str::<ErrorProbe>(body) {
+        Ok(ErrorProbe { error: Some(error) }) => MISSING_PAGE_CODES.contains(&error.code.as_str()),
+        Ok(ErrorProbe { error: None }) => true,
+        Err(_) => false,
+    }
+}
+
+/// Text of the first non-empty top-level paragraph of rendered page HTML.
+fn first_paragraph(html: &str) -> Option<String> {
+    let document = Html::parse_fragment(html);
+    let selector = Selector::parse("div.mw-parser-output > p").unwrap();
+    document.select(&selector)
+        .map(|p| p.text().collect::<String>().split_whitespace().collect::<Vec<_>>().join(" "))
+        .find(|text| !text.is_empty())
+}
+
+/// Convert a search snippet to markdown, with the matched words, which MediaWiki wraps in `searchmatch` spans, in bold.
+fn snippet_to_markdown(html: &str) -> String {
+    fn walk(element: ElementRef, out: &mut String) {
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => out.push_str(&escape(text)),
+                Node::Element(e) if e.has_class("searchmatch", scraper::CaseSensitivity::CaseSensitive) => {
+                    let text: String = ElementRef::wrap(child).map(|c| c.text().collect()).unwrap_or_default();
+                    out.push_str(&format!("**{}**", escape(&text)));
+                },
+                Node::Element(_) => {
+                    if let Some(child) = ElementRef::wrap(child) {
+                        walk(child, out);
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+    let fragment = Html::parse_fragment(html);
+    let mut out = String::new();
+    walk(fragment.root_element(), &mut out);
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::matchers::{header, method, path, query_param};
+    use wiremock::{Mock, ResponseTemplate};
+    use super::*;
+    use crate::test_support::{wiki_parse_fixture, wiki_search_fixture, MockUpstream};
+    use crate::wiki_cache::CacheConfig;
+
+    #[tokio::test]
+    async fn looks_up_pages_through_the_api() {
+        let upstream = MockUpstream::start().await;
+        upstream.serve_wiki_api(&[("action", "query"), ("srsearch", "Defiant")], wiki_search_fixture()).await;
+        upstream.serve_wiki_api(&[("action", "parse"), ("page", "Defiant")], wiki_parse_fixture()).await;
+        let client = WikiClient::new(&upstream.wiki_url(), Duration::from_secs(5), WikiCache::in_memory(Duration::from_secs(60), 10)).unwrap();
+
+        let result = client.lookup("Defiant").await.unwrap();
+        let page = result.page.unwrap();
+        assert_eq!(page.title, "Defiant Class");
+        assert_eq!(page.url, format!("{}/wiki/Defiant_Class", upstream.wiki_url()));
+        assert_eq!(page.redirected_from.as_deref(), Some("Defiant"));
+        assert_eq!(page.summary.as_deref(), Some("The Defiant Class is a Tier 3 Escort available to Federation characters."));
+        assert_eq!(page.image, Some(format!("{}/images/thumb/5/5d/Defiant.png/250px-Defiant.png", upstream.wiki_url())));
+        assert_eq!(page.infobox.unwrap().fields()[2], ("Hull".to_string(), "27,500".to_string()));
+        assert_eq!(client.file_url("Defiant Class.png"), format!("{}/wiki/Special:FilePath/Defiant_Class.png", upstream.wiki_url()));
+        assert_eq!(result.matches.len(), 2);
+        assert_eq!(result.matches[0].snippet, "The **Defiant** Class is a Tier 3 Escort");
+        assert_eq!(result.matches[1].snippet, "The **Fleet Defiant** \\*T5\\* & more");
+        assert!(result.search_url.ends_with("/index.php?title=Special%3ASearch&search=Defiant&go=Go"));
+    }
+
+    #[test]
+    fn keeps_queries_within_embed_limits() {
+        let query = format!("`{}", "Defiant ".repeat(60));
+        let result = WikiResult { query: query.clone(), page: None, matches: Vec::new(), search_url: "https://stowiki.net/search".to_string() };
+        let embed = serde_json::to_value(result.to_embed()).unwrap();
+        let title = embed["title"].as_str().unwrap();
+        assert_eq!(title.chars().count(), EMBED_TITLE_LIMIT);
+        assert!(title.starts_with("STOWiki Search: `Defiant"));
+        let description = embed["description"].as_str().unwrap();
+        assert!(description.starts_with("No pages found for \"\\`Defiant Defiant"));
+        assert!(description.chars().count() < query.chars().count());
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_suggestions_and_search_hits() {
+        let upstream = MockUpstream::start().await;
+        let suggestion = r#"{"batchcomplete": true, "query": {"searchinfo": {"totalhits": 0, "suggestion": "defiant"}, "search": []}}"#;
+        let missing = r#"{"error": {"code": "missingtitle", "info": "The page you specified doesn't exist."}}"#;
+        upstream.serve_wiki_api(&[("action", "query"), ("srsearch", "defient")], suggestion.to_string()).await;
+        upstream.serve_wiki_api(&[("action", "query"), ("srsearch", "defiant")], wiki_search_fixture()).await;
+        upstream.serve_wiki_api(&[("action", "parse"), ("page", "defient")], missing.to_string()).await;
+        upstream.serve_wiki_api(&[("action", "parse"), ("page", "Defiant Class")], wiki_parse_fixture()).await;
+        let client = WikiClient::new(&upstream.wiki_url(), Duration::from_secs(5), WikiCache::in_memory(Duration::from_secs(60), 10)).unwrap();
+
+        let result = client.lookup("defient").await.unwrap();
+        assert_eq!(result.page.map(|p| p.title).as_deref(), Some("Defiant Class"));
+        assert_eq!(result.matches[0].title, "Defiant Class");
+        assert!(client.page("defient").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn completes_titles_from_cache() {
+        let upstream = MockUpstream::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api.php"))
+            .and(query_param("list", "prefixsearch"))
+            .and(query_param("pssearch", "Defi"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"batchcomplete": true, "query": {"prefixsearch": [{"ns": 0, "title": "Defiant Class", "pageid": 101}, {"ns": 0, "title": "Defiant Refit", "pageid": 103}]}}"#
+            ))
+            .expect(1)
+            .mount(upstream.server())
+            .await;
+        let client = WikiClient::new(&upstream.wiki_url(), Duration::from_secs(5), WikiCache::in_memory(Duration::from_secs(60), 10)).unwrap();
+
+        assert_eq!(client.complete("Defi").await.unwrap(), vec!["Defiant Class", "Defiant Refit"]);
+        // Served from the cache, whatever the case
+        assert_eq!(client.clone().complete("defi ").await.unwrap().len(), 2);
+        assert!(client.complete("  ").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn revalidates_stale_responses() {
+        let upstream = MockUpstream::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api.php"))
+            .and(header("If-None-Match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(1)
+            .mount(upstream.server())
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api.php"))
+            .and(query_param("page", "Defiant"))
+            .respond_with(ResponseTemplate::new(200).insert_header("ETag", "\"v1\"").set_body_string(wiki_parse_fixture()))
+            .expect(1)
+            .mount(upstream.server())
+            .await;
+        // Everything is stale at once, so the second lookup asks whether the page changed
+        let client = WikiClient::new(&upstream.wiki_url(), Duration::from_secs(5), WikiCache::in_memory(Duration::ZERO, 10)).unwrap();
+
+        let first = client.page("Defiant").await.unwrap().unwrap();
+        let second = client.page("Defiant").await.unwrap().unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[tokio::test]
+    async fn refetches_when_the_confirmed_response_is_gone() {
+        let upstream = MockUpstream::start().await;
+        let path_buf = std::env::temp_dir().join(format!("stobot-wiki-304-{}.db", std::process::id()));
+        let db_path = path_buf.to_string_lossy().to_string();
+        // Responses only live on disk, and the wiki's 304 arrives after the one it confirms was deleted
+        let cache_path = db_path.clone();
+        Mock::given(method("GET"))
+            .and(path("/api.php"))
+            .and(header("If-None-Match", "\"v1\""))
+            .respond_with(move |_: &wiremock::Request| {
+                rusqlite::Connection::open(&cache_path).unwrap().execute("DELETE FROM wiki_responses", []).unwrap();
+                ResponseTemplate::new(304)
+            })
+            .expect(1)
+            .mount(upstream.server())
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api.php"))
+            .and(query_param("page", "Defiant"))
+            .respond_with(ResponseTemplate::new(200).insert_header("ETag", "\"v1\"").set_body_string(wiki_parse_fixture()))
+            .expect(2)
+            .mount(upstream.server())
+            .await;
+        let config = CacheConfig { ttl: Duration::ZERO, capacity: 0, path: Some(db_path) };
+        let client = WikiClient::new(&upstream.wiki_url(), Duration::from_secs(5), WikiCache::open(&config).unwrap()).unwrap();
+
+        let first = client.page("Defiant").await.unwrap().unwrap();
+        let second = client.page("Defiant").await.unwrap().unwrap();
+        std::fs::remove_file(&path_buf).unwrap();
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,229 @@
+// Cache of STOWiki API responses, kept in memory and optionally on disk, revalidated with ETag/Last-Modified
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::logging::log_error;
+
+/// How long a response is kept on disk without the wiki confirming it, afterwards it's deleted.
+/// Stale responses are still worth keeping a while, revalidating them is cheaper than fetching them again.
+const MAX_DISK_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Settings of the [`WikiCache`].
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// How long a response is used without asking the wiki whether it changed
+    pub ttl: Duration,
+    /// Most responses kept in memory, the least recently used are dropped first
+    pub capacity: usize,
+    /// SQLite file responses are also kept in, so they survive restarts
+    pub path: Option<String>,
+}
+
+/// A response body with the validators the wiki sent along.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedResponse {
+    pub body: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// When the wiki last confirmed the body
+    pub fetched_at: DateTime<Utc>,
+}
+
+impl CachedResponse {
+    pub fn new(body: String, etag: Option<String>, last_modified: Option<String>) -> CachedResponse {
+        CachedResponse { body, etag, last_modified, fetched_at: Utc::now() }
+    }
+
+    /// Whether the body can be used without revalidating it.
+    pub fn is_fresh(&self, ttl: Duration) -> bool {
+        chrono::Duration::from_std(ttl).is_ok_and(|ttl| Utc::now() - self.fetched_at < ttl)
+    }
+}
+
+/// Responses by request URL, each with the tick it was last used at.
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<String, (u64, CachedResponse)>,
+    clock: u64,
+}
+
+impl Lru {
+    fn get(&mut self, key: &str) -> Option<CachedResponse> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.entries.get_mut(key).map(|(used, response)| {
+            *used = clock;
+            response.clone()
+        })
+    }
+
+    fn insert(&mut self, key: String, response: CachedResponse, capacity: usize) {
+        self.clock += 1;
+        if !self.entries.contains_key(&key)
+            && self.entries.len() >= capacity
+            && let Some(oldest) = self.entries.iter().min_by_key(|(_, (used, _))| *used).map(|(k, _)| k.clone()) {
+            self.entries.remove(&oldest);
+        }
+        if capacity > 0 {
+            self.entries.insert(key, (self.clock, response));
+        }
+    }
+}
+
+/// Cache of wiki API responses by request URL.
+///
+/// Failing to read or write the file is logged and otherwise ignored, the wiki is asked instead.
+pub struct WikiCache {
+    ttl: Duration,
+    capacity: usize,
+    memory: Mutex<Lru>,
+    disk: Option<Mutex<Connection>>,
+}
+
+impl WikiCache {
+    pub fn open(config: &CacheConfig) -> Result<WikiCache, rusqlite::Error> {
+        let mut cache = WikiCache::in_memory(config.ttl, config.capacity);
+        if let Some(path) = &config.path {
+            let conn = Connection::open(path)?;
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS wiki_responses (
+                    url TEXT PRIMARY KEY,
+                    body TEXT NOT NULL,
+                    etag TEXT,
+                    last_modified TEXT,
+                    fetched_at TEXT NOT NULL
+                );"
+            )?;
+            prune(&conn)?;
+            cache.disk = Some(Mutex::new(conn));
+        }
+        Ok(cache)
+    }
+
+    /// A cache that only lives in memory.
+    pub fn in_memory(ttl: Duration, capacity: usize) -> WikiCache {
+        WikiCache { ttl, capacity, memory: Mutex::default(), disk: None }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// The response to `url`, however old. Responses only found on disk are brought back into memory.
+    pub fn get(&self, url: &str) -> Option<CachedResponse> {
+        if let Some(response) = self.memory.lock().unwrap().get(url) {
+            return Some(response);
+        }
+        let response = self.read(url)?;
+        self.memory.lock().unwrap().insert(url.to_string(), response.clone(), self.capacity);
+        Some(response)
+    }
+
+    pub fn insert(&self, url: &str, response: CachedResponse) {
+        self.write(url, &response);
+        self.memory.lock().unwrap().insert(url.to_string(), response, self.capacity);
+    }
+
+    /// Mark the response to `url` as confirmed by the wiki just now, returning it.
+    pub fn revalidate(&self, url: &str) -> Option<CachedResponse> {
+        let mut response = self.get(url)?;
+        response.fetched_at = Utc::now();
+        self.insert(url, response.clone());
+        Some(response)
+    }
+
+    fn read(&self, url: &str) -> Option<CachedResponse> {
+        let conn = self.disk.as_ref()?.lock().unwrap();
+        let row = conn.query_row(
+            "SELECT body, etag, last_modified, fetched_at FROM wiki_responses WHERE url = ?1",
+            params![url],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get::<_, String>(3)?)),
+        ).optional();
+        match row {
+            Ok(Some((body, etag, last_modified, fetched_at))) => {
+                // An unreadable time makes the response stale, so it is revalidated before use
+                let fetched_at = DateTime::parse_from_rfc3339(&fetched_at).map_or(DateTime::UNIX_EPOCH, |t| t.with_timezone(&Utc));
+                Some(CachedResponse { body, etag, last_modified, fetched_at })
+            },
+            Ok(None) => None,
+            Err(e) => {
+                log_error("Reading the STOWiki cache", e);
+                None
+            },
+        }
+    }
+
+    fn write(&self, url: &str, response: &CachedResponse) {
+        let Some(disk) = &self.disk else {
+            return;
+        };
+        let conn = disk.lock().unwrap();
+        let result = conn.execute(
+            "INSERT OR REPLACE INTO wiki_responses (url, body, etag, last_modified, fetched_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![url, response.body, response.etag, response.last_modified, response.fetched_at.to_rfc3339()],
+        ).and_then(|_| prune(&conn));
+        if let Err(e) = result {
+            log_error("Writing the STOWiki cache", e);
+        }
+    }
+}
+
+/// Delete the responses the wiki hasn't confirmed for longer than [`MAX_DISK_AGE`].
+fn prune(conn: &Connection) -> Result<(), rusqlite::Error> {
+    let cutoff = Utc::now() - chrono::Duration::from_std(MAX_DISK_AGE).unwrap();
+    conn.execute("DELETE FROM wiki_responses WHERE fetched_at < ?1", params![cutoff.to_rfc3339()])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used_and_persists() {
+        let cache = WikiCache::in_memory(Duration::from_secs(60), 2);
+        cache.insert("a", CachedResponse::new("A".to_string(), None, None));
+        cache.insert("b", CachedResponse::new("B".to_string(), None, None));
+        assert!(cache.get("a").is_some());
+        cache.insert("c", CachedResponse::new("C".to_string(), None, None));
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").unwrap().is_fresh(cache.ttl()));
+
+        let path = std::env::temp_dir().join(format!("stobot-wiki-cache-{}.db", std::process::id()));
+        let config = CacheConfig { ttl: Duration::ZERO, capacity: 10, path: Some(path.to_string_lossy().to_string()) };
+        WikiCache::open(&config).unwrap()
+            .insert("a", CachedResponse::new("A".to_string(), Some("\"v1\"".to_string()), None));
+        let reopened = WikiCache::open(&config).unwrap().get("a").unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reopened.body, "A");
+        assert_eq!(reopened.etag.as_deref(), Some("\"v1\""));
+        assert!(!reopened.is_fresh(config.ttl));
+    }
+
+    #[test]
+    fn expires_old_responses_on_disk() {
+        let path = std::env::temp_dir().join(format!("stobot-wiki-cache-expiry-{}.db", std::process::id()));
+        let config = CacheConfig { ttl: Duration::ZERO, capacity: 10, path: Some(path.to_string_lossy().to_string()) };
+        let cache = WikiCache::open(&config).unwrap();
+        let expired = (Utc::now() - chrono::Duration::from_std(MAX_DISK_AGE).unwrap() - chrono::Duration::days(1)).to_rfc3339();
+        let store_expired = || Connection::open(&path).unwrap()
+            .execute("INSERT OR REPLACE INTO wiki_responses (url, body, fetched_at) VALUES ('old', 'Old', ?1)", params![expired])
+            .unwrap();
+
+        // Expired when the cache is opened
+        store_expired();
+        drop(cache);
+        let cache = WikiCache::open(&config).unwrap();
+        assert!(cache.get("old").is_none());
+        // And whenever something is written
+        store_expired();
+        cache.insert("new", CachedResponse::new("New".to_string(), None, None));
+        assert!(cache.read("old").is_none());
+        assert!(cache.read("new").is_some());
+        drop(cache);
+        std::fs::remove_file(&path).unwrap();
+    }
+}